@@ -0,0 +1,898 @@
+// Assembles CHIPPER syntax (the assembler the programs in games/SOURCES were
+// written for) into ROM images.
+//
+// A line is an optional label, an instruction or directive, and a comment after
+// a `;`. Labels end in a colon, or can leave it off if they start in the first
+// column. Symbols are case-insensitive. Numbers are decimal, `#` hex or `$`
+// binary, where `.` can stand in for a 0 so sprites look like sprites
+// (`DB $.1111..`), and expressions can use `+ - * / \ % < > & ^ | ~` and
+// parentheses, with `?` (or `.`) for the address of the current line.
+//
+// Directives:
+//
+//   NAME EQU expr, NAME = expr   define a symbol (`=` can redefine it later)
+//   DB, DW, DA                   bytes, big-endian words, 'quoted' strings
+//   DS count                     reserve count zero bytes
+//   ORG address                  carry on assembling at address, #200 to #FFFF
+//   ALIGN ON/OFF                 put instructions at even addresses (default on)
+//   OPTION CHIP8/CHIP48/SCHIP10/SCHIP11/XOCHIP
+//                                only accept instructions for that machine;
+//                                without one, everything up to XO-CHIP goes
+//   OPTION BINARY                accepted, as a binary is all we ever output
+//   DEFINE/UNDEF name            flags for IFDEF/IFUND name ... ELSE ... ENDIF
+//   XREF, USED                   listing controls, ignored
+//   END                          stop reading the source
+//
+// As well as CHIPPER's mnemonics (the same ones the disassembler produces), we
+// take the older CHIP-48 assembler's (MOV, SKEQ, JSR, SPRITE...) that VBRIX is
+// written with, and accept R0-RF for V0-VF.
+
+use std::collections::{HashMap, HashSet};
+
+const ORIGIN: usize = 0x200;
+const END_OF_MEMORY: usize = 0x10000;
+
+/// Assemble a program, returning the ROM image that loads at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_with_defines(source, &[])
+}
+
+/// Assemble a program with some names already `DEFINE`d, to pick between the
+/// versions a source can build (BLINKY builds for SUPER-CHIP with `SUPER`).
+pub fn assemble_with_defines(source: &str, defines: &[String]) -> Result<Vec<u8>, String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let defines: HashSet<String> = defines.iter().map(|name| name.to_uppercase()).collect();
+
+    // The first pass works out where every label is, the second has all of them
+    // to hand and writes the program out.
+    let mut assembler = Assembler::new(defines.clone(), HashMap::new(), false);
+    assembler.run(&lines)?;
+    let mut assembler = Assembler::new(defines, assembler.symbols, true);
+    assembler.run(&lines)?;
+
+    Ok(assembler.output)
+}
+
+// Which machine's instructions OPTION allows, in order of each being a
+// superset of the one before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Chip8,
+    SuperChip10,
+    SuperChip11,
+    XoChip,
+}
+
+impl Target {
+    fn name(self) -> &'static str {
+        match self {
+            Target::Chip8 => "CHIP-8",
+            Target::SuperChip10 => "SUPER-CHIP 1.0",
+            Target::SuperChip11 => "SUPER-CHIP 1.1",
+            Target::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+struct Assembler {
+    // set on the second pass, when every symbol has to be known
+    final_pass: bool,
+    symbols: HashMap<String, i64>,
+    defines: HashSet<String>,
+    // whether each enclosing IFDEF/IFUND (or its ELSE) is being assembled
+    conditions: Vec<bool>,
+    target: Target,
+    align: bool,
+    address: usize,
+    // address of the line being assembled, for `?`
+    line_address: usize,
+    // the CHIP-48 assembler worked through expressions from the right, so
+    // a-b-c was a-(b-c), and VBRIX was built with that
+    right_to_left: bool,
+    // labels on lines of their own, which take the address of whatever comes
+    // next (after aligning it)
+    pending_labels: Vec<String>,
+    output: Vec<u8>,
+}
+
+struct Statement {
+    label: Option<String>,
+    operation: Option<String>,
+    operands: Vec<String>,
+}
+
+enum Operand {
+    Register(u16),
+    // V0 - V3, or v0-v3
+    Range(u16, u16),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Rpl,
+    Long(String),
+    Expression(String),
+}
+
+impl Assembler {
+    fn new(defines: HashSet<String>, symbols: HashMap<String, i64>, final_pass: bool) -> Self {
+        Assembler {
+            final_pass,
+            symbols,
+            defines,
+            conditions: vec![],
+            target: Target::XoChip,
+            align: true,
+            address: ORIGIN,
+            line_address: ORIGIN,
+            right_to_left: false,
+            pending_labels: vec![],
+            output: vec![],
+        }
+    }
+
+    fn run(&mut self, lines: &[&str]) -> Result<(), String> {
+        for (number, line) in lines.iter().enumerate() {
+            match self.line(line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(message) => return Err(format!("line {}: {}", number + 1, message)),
+            }
+        }
+        if !self.conditions.is_empty() {
+            return Err(String::from("IFDEF without a matching ENDIF"));
+        }
+        self.place_labels()
+    }
+
+    // Assemble one line, returning false once we reach END.
+    fn line(&mut self, line: &str) -> Result<bool, String> {
+        let statement = parse_line(line);
+        let active = self.conditions.iter().all(|&on| on);
+        let operation = statement.operation.as_deref().unwrap_or("");
+
+        match operation {
+            "IFDEF" | "IFUND" | "IFNDEF" | "ELSE" | "ENDIF" => {
+                if active {
+                    if let Some(label) = statement.label {
+                        self.pending_labels.push(label);
+                    }
+                }
+                return self.condition(operation, &statement.operands).map(|_| true);
+            }
+            _ if !active => return Ok(true),
+            "=" | "EQU" => {
+                let name = statement
+                    .label
+                    .ok_or_else(|| format!("{} needs a name to define", operation))?;
+                self.line_address = self.address;
+                let value = self.evaluate(one_operand(&statement.operands)?)?;
+                if operation == "EQU" && !self.final_pass && self.symbols.contains_key(&name) {
+                    return Err(format!("{} is already defined", name));
+                }
+                // anything still undefined on the final pass is an error from
+                // evaluate(), so this only skips forward references on the first
+                if let Some(value) = value {
+                    self.symbols.insert(name, value);
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        if let Some(label) = statement.label {
+            self.pending_labels.push(label);
+        }
+        let operands = &statement.operands;
+        let word = |text: &str| text.to_uppercase();
+
+        match operation {
+            "" => {}
+            "END" => return Ok(false),
+            "OPTION" => {
+                for option in operands {
+                    match word(option).as_str() {
+                        "BINARY" => {}
+                        "CHIP8" | "CHIP48" => self.target = Target::Chip8,
+                        "SCHIP10" => self.target = Target::SuperChip10,
+                        "SCHIP11" => self.target = Target::SuperChip11,
+                        "XOCHIP" => self.target = Target::XoChip,
+                        "HPASC" | "HPHEAD" | "STRING" => {
+                            return Err(format!("Only binary output is supported, not {}", option))
+                        }
+                        _ => return Err(format!("Unknown option {}", option)),
+                    }
+                }
+            }
+            "ALIGN" => match word(one_operand(operands)?).as_str() {
+                "ON" => self.align = true,
+                "OFF" => self.align = false,
+                other => return Err(format!("ALIGN should be ON or OFF, not {}", other)),
+            },
+            "DEFINE" => {
+                self.defines.insert(word(one_operand(operands)?));
+            }
+            "UNDEF" => {
+                self.defines.remove(&word(one_operand(operands)?));
+            }
+            "XREF" | "USED" => {}
+            "ORG" => {
+                self.place_labels()?;
+                self.line_address = self.address;
+                // XO-CHIP programs can put things anywhere in 64K, not just
+                // where an instruction can reach
+                self.address = self.value(one_operand(operands)?, 0, 0xFFFF)? as usize;
+            }
+            "DS" => {
+                self.place_labels()?;
+                self.line_address = self.address;
+                let count = self
+                    .evaluate(one_operand(operands)?)?
+                    .ok_or("DS needs a size that's defined before it")?;
+                if count < 0 {
+                    return Err(String::from("DS needs a positive size"));
+                }
+                // check before making the space, which could be enormous
+                if self.address as u64 + count as u64 > END_OF_MEMORY as u64 {
+                    return Err(String::from("Program runs past the end of memory"));
+                }
+                self.emit(&vec![0; count as usize])?;
+            }
+            "DB" | "DA" => {
+                self.place_labels()?;
+                self.line_address = self.address;
+                let mut bytes = vec![];
+                // CHIPPER lets a list of bytes end in a stray comma
+                for operand in operands.iter().filter(|operand| !operand.is_empty()) {
+                    match string_literal(operand) {
+                        Some(text) => bytes.extend(text.bytes()),
+                        None => bytes.push(self.value(operand, -128, 255)? as u8),
+                    }
+                }
+                self.emit(&bytes)?;
+            }
+            "DW" => {
+                self.place_labels()?;
+                self.line_address = self.address;
+                let mut bytes = vec![];
+                for operand in operands {
+                    let value = self.value(operand, -32768, 65535)? as u16;
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+                self.emit(&bytes)?;
+            }
+            _ => {
+                if self.align && self.address % 2 == 1 {
+                    self.emit(&[0])?;
+                }
+                self.place_labels()?;
+                self.line_address = self.address;
+                self.right_to_left = CHIP48_MNEMONICS.contains(&operation);
+                let words = self.instruction(operation, operands)?;
+                self.right_to_left = false;
+                let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+                self.emit(&bytes)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn condition(&mut self, operation: &str, operands: &[String]) -> Result<(), String> {
+        match operation {
+            "IFDEF" | "IFUND" | "IFNDEF" => {
+                let defined = self
+                    .defines
+                    .contains(&one_operand(operands)?.to_uppercase());
+                self.conditions.push(defined == (operation == "IFDEF"));
+            }
+            "ELSE" => {
+                let on = self.conditions.pop().ok_or("ELSE without an IFDEF")?;
+                self.conditions.push(!on);
+            }
+            _ => {
+                self.conditions.pop().ok_or("ENDIF without an IFDEF")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn place_labels(&mut self) -> Result<(), String> {
+        for label in std::mem::take(&mut self.pending_labels) {
+            // 15PUZZLE has a `START+1:` (which isn't even at START+1), that
+            // CHIPPER takes as a comment
+            if !is_identifier(&label) {
+                continue;
+            }
+            if !self.final_pass && self.symbols.contains_key(&label) {
+                return Err(format!("{} is already defined", label));
+            }
+            self.symbols.insert(label, self.address as i64);
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let end = self.address + bytes.len();
+        if end > END_OF_MEMORY {
+            return Err(String::from("Program runs past the end of memory"));
+        }
+        if self.address < ORIGIN && !bytes.is_empty() {
+            return Err(format!(
+                "Program starts at #{:03X}, so nothing can go at #{:03X}",
+                ORIGIN, self.address
+            ));
+        }
+        if self.final_pass && !bytes.is_empty() {
+            if self.output.len() < end - ORIGIN {
+                self.output.resize(end - ORIGIN, 0);
+            }
+            self.output[self.address - ORIGIN..end - ORIGIN].copy_from_slice(bytes);
+        }
+        self.address = end;
+        Ok(())
+    }
+
+    // The opcode(s) for an instruction. Only the XO-CHIP long load is two words.
+    fn instruction(&self, mnemonic: &str, operands: &[String]) -> Result<Vec<u16>, String> {
+        use Operand::*;
+
+        let operands: Vec<Operand> = operands.iter().map(|o| parse_operand(o)).collect();
+        let needs = |target: Target| {
+            if self.target < target {
+                Err(format!(
+                    "{} is a {} instruction, but the OPTION is {}",
+                    mnemonic,
+                    target.name(),
+                    self.target.name()
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        let xy = |opcode: u16, x: u16, y: u16| opcode | x << 8 | y << 4;
+
+        let opcode = match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) | ("RTS", []) => 0x00EE,
+            ("SCD", [Expression(n)]) | ("SCDOWN", [Expression(n)]) => {
+                needs(Target::SuperChip11)?;
+                0x00C0 | self.nibble(n)?
+            }
+            ("SCU", [Expression(n)]) => {
+                needs(Target::XoChip)?;
+                0x00D0 | self.nibble(n)?
+            }
+            ("SCR", []) | ("SCRIGHT", []) => {
+                needs(Target::SuperChip11)?;
+                0x00FB
+            }
+            ("SCL", []) | ("SCLEFT", []) => {
+                needs(Target::SuperChip11)?;
+                0x00FC
+            }
+            ("EXIT", []) | ("HALT", []) => {
+                needs(Target::SuperChip10)?;
+                0x00FD
+            }
+            ("LOW", []) => {
+                needs(Target::SuperChip10)?;
+                0x00FE
+            }
+            ("HIGH", []) => {
+                needs(Target::SuperChip10)?;
+                0x00FF
+            }
+            ("SYS", [Expression(a)]) => self.address_operand(a)? as u16,
+            ("JP", [Expression(a)]) | ("JMP", [Expression(a)]) => {
+                0x1000 | self.address_operand(a)? as u16
+            }
+            ("JP", [Register(0), Expression(a)]) | ("JMI", [Expression(a)]) => {
+                0xB000 | self.address_operand(a)? as u16
+            }
+            ("CALL", [Expression(a)]) | ("JSR", [Expression(a)]) => {
+                0x2000 | self.address_operand(a)? as u16
+            }
+            ("SE", [Register(x), Register(y)]) | ("SKEQ", [Register(x), Register(y)]) => {
+                xy(0x5000, *x, *y)
+            }
+            ("SE", [Register(x), Expression(k)]) | ("SKEQ", [Register(x), Expression(k)]) => {
+                xy(0x3000, *x, 0) | self.byte(k)?
+            }
+            ("SNE", [Register(x), Register(y)]) | ("SKNE", [Register(x), Register(y)]) => {
+                xy(0x9000, *x, *y)
+            }
+            ("SNE", [Register(x), Expression(k)]) | ("SKNE", [Register(x), Expression(k)]) => {
+                xy(0x4000, *x, 0) | self.byte(k)?
+            }
+            ("SAVE", [Range(x, y)]) => {
+                needs(Target::XoChip)?;
+                xy(0x5002, *x, *y)
+            }
+            ("LOAD", [Range(x, y)]) => {
+                needs(Target::XoChip)?;
+                xy(0x5003, *x, *y)
+            }
+            ("LD", [Register(x), Register(y)]) | ("MOV", [Register(x), Register(y)]) => {
+                xy(0x8000, *x, *y)
+            }
+            ("LD", [Register(x), Expression(k)]) | ("MOV", [Register(x), Expression(k)]) => {
+                xy(0x6000, *x, 0) | self.byte(k)?
+            }
+            ("ADD", [Register(x), Register(y)]) => xy(0x8004, *x, *y),
+            ("ADD", [Register(x), Expression(k)]) => xy(0x7000, *x, 0) | self.byte(k)?,
+            ("OR", [Register(x), Register(y)]) => xy(0x8001, *x, *y),
+            ("AND", [Register(x), Register(y)]) => xy(0x8002, *x, *y),
+            ("XOR", [Register(x), Register(y)]) => xy(0x8003, *x, *y),
+            ("SUB", [Register(x), Register(y)]) => xy(0x8005, *x, *y),
+            ("SUBN", [Register(x), Register(y)]) | ("RSB", [Register(x), Register(y)]) => {
+                xy(0x8007, *x, *y)
+            }
+            ("SHR", [Register(x)]) => xy(0x8006, *x, 0),
+            ("SHR", [Register(x), Register(y)]) => xy(0x8006, *x, *y),
+            ("SHL", [Register(x)]) => xy(0x800E, *x, 0),
+            ("SHL", [Register(x), Register(y)]) => xy(0x800E, *x, *y),
+            ("LD", [I, Long(a)]) => {
+                needs(Target::XoChip)?;
+                return Ok(vec![0xF000, self.value(a, 0, 0xFFFF)? as u16]);
+            }
+            ("LD", [I, Expression(a)]) | ("MVI", [Expression(a)]) => {
+                0xA000 | self.address_operand(a)? as u16
+            }
+            ("RND", [Register(x), Expression(k)])
+            | ("RAND", [Register(x), Expression(k)])
+            | ("RANDOM", [Register(x), Expression(k)]) => xy(0xC000, *x, 0) | self.byte(k)?,
+            ("DRW", [Register(x), Register(y), Expression(n)])
+            | ("SPRITE", [Register(x), Register(y), Expression(n)]) => {
+                let n = self.nibble(n)?;
+                if n == 0 {
+                    needs(Target::SuperChip10)?;
+                }
+                xy(0xD000, *x, *y) | n
+            }
+            ("SKP", [Register(x)]) | ("SKPR", [Register(x)]) => xy(0xE09E, *x, 0),
+            ("SKNP", [Register(x)]) | ("SKUP", [Register(x)]) => xy(0xE0A1, *x, 0),
+            ("PLANE", [Expression(n)]) => {
+                needs(Target::XoChip)?;
+                0xF001 | self.nibble(n)? << 8
+            }
+            ("AUDIO", []) => {
+                needs(Target::XoChip)?;
+                0xF002
+            }
+            ("LD", [Register(x), DelayTimer]) | ("GDELAY", [Register(x)]) => xy(0xF007, *x, 0),
+            ("LD", [Register(x), Key]) | ("KEY", [Register(x)]) => xy(0xF00A, *x, 0),
+            ("LD", [DelayTimer, Register(x)]) | ("SDELAY", [Register(x)]) => xy(0xF015, *x, 0),
+            ("LD", [SoundTimer, Register(x)]) | ("SSOUND", [Register(x)]) => xy(0xF018, *x, 0),
+            ("ADD", [I, Register(x)]) | ("ADI", [Register(x)]) => xy(0xF01E, *x, 0),
+            ("LD", [Font, Register(x)]) | ("FONT", [Register(x)]) => xy(0xF029, *x, 0),
+            ("LD", [BigFont, Register(x)]) | ("XFONT", [Register(x)]) => {
+                needs(Target::SuperChip10)?;
+                xy(0xF030, *x, 0)
+            }
+            ("LD", [Bcd, Register(x)]) | ("BCD", [Register(x)]) => xy(0xF033, *x, 0),
+            ("PITCH", [Register(x)]) => {
+                needs(Target::XoChip)?;
+                xy(0xF03A, *x, 0)
+            }
+            ("LD", [IndirectI, Register(x)]) | ("STR", [Range(0, x)]) => xy(0xF055, *x, 0),
+            ("LD", [Register(x), IndirectI]) | ("LDR", [Range(0, x)]) => xy(0xF065, *x, 0),
+            ("LD", [Rpl, Register(x)]) => {
+                needs(Target::SuperChip10)?;
+                if *x > 7 {
+                    return Err(String::from("Only V0-V7 can be saved to the RPL flags"));
+                }
+                xy(0xF075, *x, 0)
+            }
+            ("LD", [Register(x), Rpl]) => {
+                needs(Target::SuperChip10)?;
+                if *x > 7 {
+                    return Err(String::from("Only V0-V7 can be loaded from the RPL flags"));
+                }
+                xy(0xF085, *x, 0)
+            }
+            _ if is_mnemonic(mnemonic) => {
+                return Err(format!("Wrong operands for {}", mnemonic));
+            }
+            _ => return Err(format!("Unknown instruction {}", mnemonic)),
+        };
+
+        Ok(vec![opcode])
+    }
+
+    fn nibble(&self, expression: &str) -> Result<u16, String> {
+        Ok(self.value(expression, 0, 15)? as u16)
+    }
+
+    fn byte(&self, expression: &str) -> Result<u16, String> {
+        Ok(self.value(expression, -128, 255)? as u8 as u16)
+    }
+
+    fn address_operand(&self, expression: &str) -> Result<usize, String> {
+        Ok(self.value(expression, 0, 0xFFF)? as usize)
+    }
+
+    // Evaluate an operand that has to fit between min and max. On the first
+    // pass, symbols that aren't defined yet just come out as 0.
+    fn value(&self, expression: &str, min: i64, max: i64) -> Result<i64, String> {
+        match self.evaluate(expression)? {
+            Some(value) if value < min || value > max => Err(format!(
+                "{} is {}, which is out of range ({} to {})",
+                expression.trim(),
+                value,
+                min,
+                max
+            )),
+            Some(value) => Ok(value),
+            None => Ok(0),
+        }
+    }
+
+    // The value of an expression, or None on the first pass if it uses a
+    // symbol we haven't seen yet.
+    fn evaluate(&self, expression: &str) -> Result<Option<i64>, String> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            assembler: self,
+            undefined: false,
+        };
+        let value = parser.expression(0)?;
+        if parser.position != tokens.len() {
+            return Err(format!("Can't make sense of '{}'", expression.trim()));
+        }
+        Ok(if parser.undefined { None } else { Some(value) })
+    }
+
+    fn symbol(&self, name: &str) -> Result<Option<i64>, String> {
+        match self.symbols.get(name) {
+            Some(value) => Ok(Some(*value)),
+            None if self.final_pass => Err(format!("{} isn't defined", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+// Split a line into its label, operation and operands.
+fn parse_line(line: &str) -> Statement {
+    let line = strip_comment(line);
+    let starts_in_first_column = !line.starts_with(|c: char| c.is_whitespace());
+    let mut rest = line.trim();
+    let mut label = None;
+
+    let first_word = rest.split_whitespace().next().unwrap_or("");
+    let (first, after) = split_identifier(rest);
+    if let Some(colon) = first_word.find(':') {
+        label = Some(first_word[..colon].to_uppercase());
+        rest = rest[colon + 1..].trim();
+    } else if !first.is_empty() {
+        let after_trimmed = after.trim_start();
+        if let Some(after_colon) = after_trimmed.strip_prefix(':') {
+            label = Some(first.to_uppercase());
+            rest = after_colon.trim();
+        } else if after_trimmed.starts_with('=')
+            || !is_mnemonic(&first.to_uppercase())
+                && (starts_in_first_column || !after_trimmed.is_empty())
+        {
+            label = Some(first.to_uppercase());
+            rest = after_trimmed;
+        }
+    }
+
+    let (operation, operands) = if let Some(value) = rest.strip_prefix('=') {
+        (Some(String::from("=")), value)
+    } else {
+        let (operation, operands) = split_identifier(rest);
+        if operation.is_empty() {
+            (None, rest)
+        } else {
+            (Some(operation.to_uppercase()), operands)
+        }
+    };
+
+    Statement {
+        label,
+        operation,
+        operands: split_operands(operands),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let (identifier, rest) = split_identifier(text);
+    rest.is_empty() && identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+fn split_identifier(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+// Split operands on commas, leaving any inside quotes alone.
+fn split_operands(text: &str) -> Vec<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return vec![];
+    }
+    let mut operands = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => operands.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    operands.push(current.trim().to_string());
+    operands
+}
+
+// The contents of a quoted string longer than one character ('' is a quote).
+// Single characters are left to the expression parser, as they're numbers.
+fn string_literal(operand: &str) -> Option<String> {
+    let inner = operand.strip_prefix('\'')?.strip_suffix('\'')?;
+    let text = inner.replace("''", "'");
+    if text.chars().count() > 1 {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+fn register(text: &str) -> Option<u16> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V'), Some(digit), None) | (Some('R'), Some(digit), None) => {
+            digit.to_digit(16).map(|d| d as u16)
+        }
+        _ => None,
+    }
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let upper = text.to_uppercase();
+    match upper.as_str() {
+        "I" => return Operand::I,
+        "[I]" => return Operand::IndirectI,
+        "DT" => return Operand::DelayTimer,
+        "ST" => return Operand::SoundTimer,
+        "K" => return Operand::Key,
+        "F" => return Operand::Font,
+        "HF" => return Operand::BigFont,
+        "B" => return Operand::Bcd,
+        "R" => return Operand::Rpl,
+        _ => {}
+    }
+    if let Some(register) = register(&upper) {
+        return Operand::Register(register);
+    }
+    if let Some(address) = upper.strip_prefix("LONG ") {
+        return Operand::Long(address.to_string());
+    }
+    let mut ends = upper.splitn(2, '-').map(|end| register(end.trim()));
+    if let (Some(Some(first)), Some(Some(last))) = (ends.next(), ends.next()) {
+        return Operand::Range(first, last);
+    }
+    Operand::Expression(text.to_string())
+}
+
+fn one_operand(operands: &[String]) -> Result<&str, String> {
+    match operands {
+        [operand] => Ok(operand),
+        _ => Err(String::from("Expected one operand")),
+    }
+}
+
+const DIRECTIVES: &[&str] = &[
+    "EQU", "DB", "DW", "DA", "DS", "ORG", "ALIGN", "OPTION", "DEFINE", "UNDEF", "IFDEF", "IFUND",
+    "IFNDEF", "ELSE", "ENDIF", "XREF", "USED", "END",
+];
+
+const CHIPPER_MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS", "JP", "CALL", "SE",
+    "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND",
+    "DRW", "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+
+const CHIP48_MNEMONICS: &[&str] = &[
+    "RTS", "SCDOWN", "SCRIGHT", "SCLEFT", "HALT", "JMP", "JMI", "JSR", "SKEQ", "SKNE", "MOV",
+    "RSB", "MVI", "RAND", "RANDOM", "SPRITE", "SKPR", "SKUP", "GDELAY", "KEY", "SDELAY", "SSOUND",
+    "ADI", "FONT", "XFONT", "BCD", "STR", "LDR",
+];
+
+fn is_mnemonic(word: &str) -> bool {
+    DIRECTIVES.contains(&word)
+        || CHIPPER_MNEMONICS.contains(&word)
+        || CHIP48_MNEMONICS.contains(&word)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Here,
+    Operator(char),
+    Open,
+    Close,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    // the run of characters starting at position that satisfy accept
+    let take = |position: &mut usize, accept: &dyn Fn(char) -> bool| {
+        let start = *position;
+        while *position < chars.len() && accept(chars[*position]) {
+            *position += 1;
+        }
+        chars[start..*position].iter().collect::<String>()
+    };
+
+    while position < chars.len() {
+        let c = chars[position];
+        match c {
+            _ if c.is_whitespace() => position += 1,
+            '#' | '$' => {
+                position += 1;
+                let (digits, radix) = if c == '#' {
+                    (take(&mut position, &|c| c.is_ascii_hexdigit()), 16)
+                } else {
+                    let digits = take(&mut position, &|c| c == '0' || c == '1' || c == '.');
+                    (digits.replace('.', "0"), 2)
+                };
+                let value = i64::from_str_radix(&digits, radix)
+                    .map_err(|_| format!("Bad number in '{}'", expression.trim()))?;
+                tokens.push(Token::Number(value));
+            }
+            _ if c.is_ascii_digit() => {
+                let digits = take(&mut position, &|c| c.is_ascii_digit());
+                let value = digits
+                    .parse()
+                    .map_err(|_| format!("Bad number in '{}'", expression.trim()))?;
+                tokens.push(Token::Number(value));
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let name = take(&mut position, &|c| c.is_ascii_alphanumeric() || c == '_');
+                tokens.push(Token::Symbol(name.to_uppercase()));
+            }
+            '\'' if position + 2 < chars.len() && chars[position + 2] == '\'' => {
+                tokens.push(Token::Number(chars[position + 1] as i64));
+                position += 3;
+            }
+            '?' | '.' => {
+                tokens.push(Token::Here);
+                position += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                position += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                position += 1;
+            }
+            '+' | '-' | '*' | '/' | '\\' | '%' | '<' | '>' | '&' | '^' | '|' | '~' => {
+                tokens.push(Token::Operator(c));
+                position += 1;
+            }
+            _ => return Err(format!("Unexpected '{}' in '{}'", c, expression.trim())),
+        }
+    }
+
+    Ok(tokens)
+}
+
+// How tightly each binary operator binds; < and > are shifts. CHIPPER's `\`
+// divides whatever is to the left of it, so `END - START \ 4` is the number of
+// four-byte entries in a table.
+fn precedence(operator: char) -> Option<u8> {
+    match operator {
+        '\\' => Some(1),
+        '|' => Some(2),
+        '^' => Some(3),
+        '&' => Some(4),
+        '<' | '>' => Some(5),
+        '+' | '-' => Some(6),
+        '*' | '/' | '%' => Some(7),
+        _ => None,
+    }
+}
+
+// A precedence-climbing expression parser.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    assembler: &'a Assembler,
+    // set if the expression used a symbol that isn't defined yet
+    undefined: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn expression(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut value = self.unary()?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            let precedence = match precedence(operator) {
+                Some(precedence) if precedence > min_precedence => precedence,
+                _ => break,
+            };
+            self.position += 1;
+            let right = if self.assembler.right_to_left {
+                self.expression(precedence - 1)?
+            } else {
+                self.expression(precedence)?
+            };
+            value = match operator {
+                '|' => value | right,
+                '^' => value ^ right,
+                '&' => value & right,
+                '<' => value.checked_shl(right as u32).unwrap_or(0),
+                '>' => value.checked_shr(right as u32).unwrap_or(0),
+                '+' => value.wrapping_add(right),
+                '-' => value.wrapping_sub(right),
+                '*' => value.wrapping_mul(right),
+                '%' if right != 0 => value % right,
+                _ if right != 0 => value / right,
+                _ if self.undefined => 0,
+                _ => return Err(String::from("Division by zero")),
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("Expected a value")?;
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(value),
+            Token::Here => Ok(self.assembler.line_address as i64),
+            Token::Symbol(name) => match self.assembler.symbol(&name)? {
+                Some(value) => Ok(value),
+                None => {
+                    self.undefined = true;
+                    Ok(0)
+                }
+            },
+            Token::Operator('-') => Ok(self.unary()?.wrapping_neg()),
+            Token::Operator('+') => self.unary(),
+            Token::Operator('~') => Ok(!self.unary()?),
+            Token::Open => {
+                let value = self.expression(0)?;
+                if self.tokens.get(self.position) != Some(&Token::Close) {
+                    return Err(String::from("Missing )"));
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(String::from("Expected a value")),
+        }
+    }
+}
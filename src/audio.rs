@@ -0,0 +1,289 @@
+// The only sound CHIP-8 can make is a beep that plays for as long as the sound
+// timer is non-zero. The VM doesn't know anything about audio devices; each
+// frontend hands it an AudioSink and tells it once a frame whether to beep.
+//
+// XO-CHIP programs can swap the beep for a pattern of their own: 128 one-bit
+// samples, played in a loop at a rate set by the pitch register. Once a program
+// loads one, the frontend passes it on to the sink before each frame.
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// The shape of the beep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// Look up a waveform by name (case insensitive), e.g. "square" or "sine".
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+/// What the beep sounds like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Pitch, in Hz.
+    pub frequency: f32,
+    /// Loudness, from 0.0 (silent) to 1.0 (as loud as it goes).
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// Generates samples of a [`Tone`], or of an XO-CHIP audio pattern, at a given
+/// sample rate.
+#[derive(Debug, Clone)]
+pub struct Oscillator {
+    tone: Tone,
+    sample_rate: u32,
+    // how far through the current wave (or pattern) we are, from 0.0 to 1.0
+    phase: f32,
+    // the pattern to play instead of the tone, and its bits per second
+    pattern: Option<([u8; 16], f64)>,
+}
+
+impl Oscillator {
+    pub fn new(tone: Tone, sample_rate: u32) -> Oscillator {
+        Oscillator {
+            tone,
+            sample_rate,
+            phase: 0.0,
+            pattern: None,
+        }
+    }
+
+    /// Play an XO-CHIP audio pattern from now on instead of the tone, at `rate`
+    /// bits per second. The tone's volume still applies.
+    pub fn set_pattern(&mut self, pattern: [u8; 16], rate: f64) {
+        self.pattern = Some((pattern, rate));
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let (value, frequency) = match self.pattern {
+            Some((pattern, rate)) => {
+                // the bits go most significant first, and the whole pattern is one wave
+                let bit = (self.phase * 128.0) as usize % 128;
+                let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                (if on { 1.0 } else { -1.0 }, (rate / 128.0) as f32)
+            }
+            None => {
+                let value = match self.tone.waveform {
+                    Waveform::Square => {
+                        if self.phase < 0.5 {
+                            1.0
+                        } else {
+                            -1.0
+                        }
+                    }
+                    Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+                    Waveform::Sawtooth => 2.0 * self.phase - 1.0,
+                    Waveform::Sine => (2.0 * PI * self.phase).sin(),
+                };
+                (value, self.tone.frequency)
+            }
+        };
+
+        self.phase = (self.phase + frequency / self.sample_rate as f32) % 1.0;
+
+        value * self.tone.volume
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+/// Somewhere for the beep to go.
+pub trait AudioSink {
+    /// Called once per 60 Hz frame, with whether the sound timer is running.
+    fn frame(&mut self, playing: bool);
+
+    /// Called before each frame once the program has loaded an XO-CHIP audio
+    /// pattern, with the pattern and how many of its bits to play per second
+    /// (see [`crate::Chip8::audio_playback_rate`]). It replaces the beep. Sinks
+    /// that can only beep can leave this out.
+    fn pattern(&mut self, _pattern: &[u8; 16], _rate: f64) {}
+}
+
+/// Renders the beep into memory instead of playing it, so it can be saved as a
+/// WAV file. Useful for headless runs and for testing.
+pub struct WavSink {
+    oscillator: Oscillator,
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+impl WavSink {
+    pub fn new(tone: Tone, sample_rate: u32) -> WavSink {
+        WavSink {
+            oscillator: Oscillator::new(tone, sample_rate),
+            sample_rate,
+            samples: vec![],
+        }
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Write everything rendered so far as a mono, 16-bit PCM WAV file.
+    pub fn write_wav(&self, out: &mut impl Write) -> io::Result<()> {
+        let data_size = self.samples.len() as u32 * 2;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&(36 + data_size).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?; // size of this chunk
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&self.sample_rate.to_le_bytes())?;
+        out.write_all(&(self.sample_rate * 2).to_le_bytes())?; // bytes per second
+        out.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        out.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            out.write_all(&sample.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_wav(&mut file)?;
+        file.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn pattern(&mut self, pattern: &[u8; 16], rate: f64) {
+        self.oscillator.set_pattern(*pattern, rate);
+    }
+
+    fn frame(&mut self, playing: bool) {
+        for _ in 0..self.sample_rate / 60 {
+            let sample = if playing {
+                self.oscillator.next_sample()
+            } else {
+                0.0
+            };
+            self.samples.push((sample * i16::MAX as f32) as i16);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(waveform: Waveform) -> Tone {
+        Tone {
+            frequency: 1000.0,
+            volume: 1.0,
+            waveform,
+        }
+    }
+
+    // One wave of a 1000 Hz tone at 8000 samples per second.
+    fn one_wave(waveform: Waveform) -> Vec<f32> {
+        let mut oscillator = Oscillator::new(tone(waveform), 8000);
+        (0..8).map(|_| oscillator.next_sample()).collect()
+    }
+
+    #[test]
+    fn waveforms() {
+        assert_eq!(
+            one_wave(Waveform::Square),
+            [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]
+        );
+        assert_eq!(
+            one_wave(Waveform::Triangle),
+            [1.0, 0.5, 0.0, -0.5, -1.0, -0.5, 0.0, 0.5]
+        );
+        assert_eq!(
+            one_wave(Waveform::Sawtooth),
+            [-1.0, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75]
+        );
+        let sine = one_wave(Waveform::Sine);
+        assert!((sine[2] - 1.0).abs() < 1e-6);
+        assert!((sine[6] + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn volume() {
+        let mut oscillator = Oscillator::new(
+            Tone {
+                volume: 0.25,
+                ..tone(Waveform::Square)
+            },
+            8000,
+        );
+        assert_eq!(oscillator.next_sample(), 0.25);
+    }
+
+    #[test]
+    fn patterns() {
+        // alternating bytes of ones and zeros, at one bit per sample
+        let mut pattern = [0u8; 16];
+        for byte in pattern.iter_mut().step_by(2) {
+            *byte = 0xFF;
+        }
+        let mut oscillator = Oscillator::new(tone(Waveform::Sine), 8000);
+        oscillator.set_pattern(pattern, 8000.0);
+        let samples: Vec<f32> = (0..256).map(|_| oscillator.next_sample()).collect();
+        for (n, sample) in samples.iter().enumerate() {
+            let expected = if (n / 8) % 2 == 0 { 1.0 } else { -1.0 };
+            assert_eq!(*sample, expected, "sample {}", n);
+        }
+    }
+
+    #[test]
+    fn wav_file() {
+        let mut wav = WavSink::new(tone(Waveform::Square), 6000);
+        wav.frame(true);
+        wav.frame(false);
+        assert_eq!(wav.samples().len(), 200);
+        assert!(wav.samples()[..100].iter().all(|sample| *sample != 0));
+        assert!(wav.samples()[100..].iter().all(|sample| *sample == 0));
+
+        let mut out = vec![];
+        wav.write_wav(&mut out).unwrap();
+        assert_eq!(out.len(), 44 + 400);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(out[4..8], (36u32 + 400).to_le_bytes());
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(out[22..24], 1u16.to_le_bytes());
+        assert_eq!(out[24..28], 6000u32.to_le_bytes());
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(out[40..44], 400u32.to_le_bytes());
+        assert_eq!(out[44..46], i16::MAX.to_le_bytes());
+    }
+}
@@ -0,0 +1,60 @@
+// Assembles CHIPPER source into a program that the interpreter can run.
+
+use chip8_interpreter::asm::assemble_with_defines;
+use chip8_interpreter::cli::Args;
+
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: chip8-asm [options] <source>
+
+options:
+  -o, --output <file>  where to write the program (default: the source file
+                       with a .ch8 extension)
+  -D, --define <name>  DEFINE a name before assembling, for IFDEF";
+
+fn main() {
+    let mut output = None;
+    let mut defines = vec![];
+    let mut source = None;
+
+    let mut args = Args::new(String::from(USAGE));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.value(&arg)),
+            "-D" | "--define" => defines.push(args.value(&arg)),
+            "-h" | "--help" => args.help(),
+            _ if arg.starts_with('-') => args.error(&format!("Unknown option {}", arg)),
+            _ => source = Some(arg),
+        }
+    }
+
+    let path = source.unwrap_or_else(|| args.error("No source file specified!"));
+    // the sources in games/SOURCES aren't all UTF-8, and only ever use ASCII
+    // outside of comments
+    let text = match fs::read(&path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(error) => {
+            eprintln!("Couldn't read {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let program = assemble_with_defines(&text, &defines).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+
+    let output = output.unwrap_or_else(|| {
+        Path::new(&path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    if let Err(error) = fs::write(&output, &program) {
+        eprintln!("Couldn't write {}: {}", output, error);
+        process::exit(1);
+    }
+    println!("Wrote {} bytes to {}", program.len(), output);
+}
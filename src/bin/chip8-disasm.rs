@@ -0,0 +1,48 @@
+// Disassembles a program into a listing that the assembler can read back in.
+
+use chip8_interpreter::cli::Args;
+use chip8_interpreter::disasm::{disassemble_linear, disassemble_program, format_listing};
+
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: chip8-disasm [options] <rom>
+
+options:
+  --origin <hex>  address the program is loaded at (default 200)
+  --linear        treat every two bytes as an instruction, instead of
+                  following the code to tell it apart from data";
+
+fn main() {
+    let mut origin = 0x200;
+    let mut linear = false;
+    let mut rom = None;
+
+    let mut args = Args::new(String::from(USAGE));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => {
+                let value = args.value(&arg);
+                origin = u16::from_str_radix(value.trim_start_matches("0x"), 16)
+                    .unwrap_or_else(|_| args.error(&format!("Invalid origin '{}'", value)));
+            }
+            "--linear" => linear = true,
+            "-h" | "--help" => args.help(),
+            _ if arg.starts_with("--") => args.error(&format!("Unknown option {}", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    let path = rom.unwrap_or_else(|| args.error("No program specified!"));
+    let bytes = fs::read(&path).unwrap_or_else(|error| {
+        eprintln!("Couldn't read {}: {}", path, error);
+        process::exit(1);
+    });
+
+    let lines = if linear {
+        disassemble_linear(&bytes, origin)
+    } else {
+        disassemble_program(&bytes, origin)
+    };
+    print!("{}", format_listing(&lines));
+}
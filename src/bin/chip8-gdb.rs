@@ -0,0 +1,59 @@
+// Runs a program under a GDB remote stub, without a window. Connect to it with
+// `target remote localhost:1234` and debug it like any other remote target.
+
+use chip8_interpreter::cli::{Args, MachineOptions};
+use chip8_interpreter::gdb::{GdbStub, Status};
+
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: chip8-gdb [options] <rom>
+
+options:
+  --port <n>         port to listen on, on localhost (default 1234)";
+
+fn main() {
+    let mut args = Args::new(format!("{}\n{}", USAGE, MachineOptions::USAGE));
+    let mut port: u16 = 1234;
+    let mut machine = MachineOptions::default();
+    let mut rom = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.parse(&arg),
+            "-h" | "--help" => args.help(),
+            _ if machine.parse(&arg, &mut args) => {}
+            _ if arg.starts_with("--") => args.error(&format!("Unknown option {}", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    let rom = rom.unwrap_or_else(|| args.error("No program specified!"));
+    let (mut vm, instructions_per_frame) = machine.load(&rom, None).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+
+    let mut stub = GdbStub::bind(("127.0.0.1", port)).unwrap_or_else(|error| {
+        eprintln!("Couldn't listen on port {}: {}", port, error);
+        process::exit(1);
+    });
+    println!("Waiting for GDB on localhost:{}", port);
+
+    let frame_length = Duration::from_secs(1) / 60;
+    loop {
+        let frame_start = Instant::now();
+        match stub.frame(&mut vm, instructions_per_frame) {
+            Ok(Status::Killed) => break,
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("GDB connection failed: {}", error);
+                process::exit(1);
+            }
+        }
+        if let Some(remaining) = frame_length.checked_sub(frame_start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
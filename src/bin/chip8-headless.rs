@@ -0,0 +1,36 @@
+// Runs a program without a window, for CI and scripting. Loads a ROM, runs it
+// for a number of frames (or until it halts or faults), optionally feeding it
+// scripted key presses, then dumps the screen and a summary of the machine state.
+// It can also record the session as a movie, or play one back and check that it
+// still goes the same way.
+
+use chip8_interpreter::cli::{Args, HeadlessOptions, MachineOptions, SessionOptions};
+
+fn main() {
+    let mut args = Args::new(format!(
+        "usage: chip8-headless [options] <rom>\n\noptions:\n{}\n{}\n{}",
+        HeadlessOptions::USAGE,
+        MachineOptions::USAGE,
+        SessionOptions::USAGE
+    ));
+    let mut headless = HeadlessOptions::default();
+    let mut machine = MachineOptions::default();
+    let mut session = SessionOptions::default();
+    let mut rom = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            _ if headless.parse(&arg, &mut args) => {}
+            _ if machine.parse(&arg, &mut args) => {}
+            _ if session.parse(&arg, &mut args) => {}
+            _ if arg.starts_with("--") => args.error(&format!("Unknown option {}", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    let rom = rom.unwrap_or_else(|| args.error("No program specified!"));
+    session.check(&machine, &args);
+    headless.check(&session, &args);
+    headless.run(&rom, &machine, &session);
+}
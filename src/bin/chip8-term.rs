@@ -0,0 +1,312 @@
+// A frontend that draws in the terminal, for when there's no display (or no SDL2)
+// around, e.g. over SSH. Pixels are drawn with Unicode half blocks, or braille
+// with --braille for small terminals.
+
+use chip8_interpreter::audio::AudioSink;
+use chip8_interpreter::cli::{Args, MachineOptions};
+use chip8_interpreter::disasm::disassemble_at;
+use chip8_interpreter::keymap::KeymapConfig;
+use chip8_interpreter::phosphor::Phosphor;
+use chip8_interpreter::Chip8;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use std::io::{self, Write};
+use std::process;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: chip8-term [options] <rom>
+
+options:
+  --keymap <file>    which keys press what, from a keymap file
+  --braille          draw with braille instead of half blocks
+  --phosphor <0-1>   let pixels fade out over a few frames to hide flicker;
+                     how much brightness is left each frame (default 0, off)";
+
+const KEYS: &str = "keys: the keypad is on 1234/QWER/ASDF/ZXCV, P pauses, period steps
+      a single instruction while paused, and escape quits";
+
+// Terminals tell us when a key is pressed (and when it auto-repeats), but not
+// when it's let go. So a key counts as held down for this many frames after we
+// last heard about it, which is long enough to bridge the gap before repeats start.
+const KEY_HOLD_FRAMES: u32 = 30;
+
+struct Options {
+    rom: String,
+    machine: MachineOptions,
+    keymap: KeymapConfig,
+    braille: bool,
+    phosphor: f32,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        rom: String::new(),
+        machine: MachineOptions::default(),
+        keymap: KeymapConfig::default(),
+        braille: false,
+        phosphor: 0.0,
+    };
+    let mut rom = None;
+
+    let mut args = Args::new(format!("{}\n{}\n\n{}", USAGE, MachineOptions::USAGE, KEYS));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keymap" => {
+                let path = args.value(&arg);
+                options.keymap = KeymapConfig::load(&path).unwrap_or_else(|error| {
+                    args.error(&format!("Couldn't load {}: {}", path, error))
+                });
+            }
+            "--braille" => options.braille = true,
+            "--phosphor" => options.phosphor = args.parse(&arg),
+            "-h" | "--help" => args.help(),
+            _ if options.machine.parse(&arg, &mut args) => {}
+            _ if arg.starts_with("--") => args.error(&format!("Unknown option {}", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    match rom {
+        Some(rom) => options.rom = rom,
+        None => args.error("No program specified!"),
+    }
+
+    options
+}
+
+// The closest a terminal gets to a beeper: ring the bell when the sound starts.
+struct TerminalBell {
+    playing: bool,
+}
+
+impl AudioSink for TerminalBell {
+    fn frame(&mut self, playing: bool) {
+        if playing && !self.playing {
+            print!("\x07");
+        }
+        self.playing = playing;
+    }
+}
+
+// Draw the screen, then the status lines underneath it.
+fn draw(out: &mut impl Write, screen: &str, status: &str) -> io::Result<()> {
+    for (y, line) in screen
+        .lines()
+        .chain(Some(""))
+        .chain(status.lines())
+        .enumerate()
+    {
+        queue!(
+            out,
+            MoveTo(0, y as u16),
+            Print(line),
+            Clear(ClearType::UntilNewLine)
+        )?;
+    }
+    queue!(out, Clear(ClearType::FromCursorDown))?;
+    out.flush()
+}
+
+// The keymap uses SDL's names for keys. For most that's the character, and the
+// rest are whatever a terminal can tell apart. Modifiers like Left Shift never
+// come through on their own, so nothing bound to those can be pressed here.
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char(c) => return Some(c.to_string()),
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Enter => "Return",
+        KeyCode::Tab => "Tab",
+        KeyCode::Backspace => "Backspace",
+        KeyCode::Delete => "Delete",
+        KeyCode::Insert => "Insert",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        _ => return None,
+    };
+    Some(String::from(name))
+}
+
+// Returns a description of the fault, if the program faulted before we quit.
+fn run(
+    vm: &mut Chip8,
+    instructions_per_frame: usize,
+    options: &Options,
+) -> io::Result<Option<String>> {
+    let mut stdout = io::stdout();
+    let frame_time = Duration::from_nanos(10_u64.pow(9) / 60);
+    let mut bell = TerminalBell { playing: false };
+    let mut phosphor = Phosphor::new(options.phosphor);
+    let keymap = options.keymap.keymap_for(vm.rom_hash(), vm.profile());
+
+    let mut held_for = [0u32; 16];
+    let mut paused = false;
+    let mut step_instruction = false;
+    let mut fault: Option<String> = None;
+    let mut last_drawn = (String::new(), String::new());
+
+    loop {
+        let frame_start = Instant::now();
+
+        while event::poll(Duration::from_secs(0))? {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Esc => return Ok(fault),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(fault)
+                }
+                KeyCode::Char('p') | KeyCode::Char('P') | KeyCode::Pause => {
+                    paused = !paused;
+                }
+                KeyCode::Char('.') => step_instruction = true,
+                code => {
+                    let name = match key_name(code) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    for k in keymap.keys_for(&name) {
+                        held_for[k as usize] = KEY_HOLD_FRAMES;
+                    }
+                }
+            }
+        }
+
+        vm.register_keydown((0..16u8).filter(|k| held_for[*k as usize] > 0));
+        for frames in held_for.iter_mut() {
+            *frames = frames.saturating_sub(1);
+        }
+
+        let result = if paused {
+            if step_instruction && fault.is_none() {
+                vm.execute_next_instruction()
+            } else {
+                Ok(())
+            }
+        } else if fault.is_none() {
+            vm.run_frame(instructions_per_frame)
+        } else {
+            Ok(())
+        };
+        step_instruction = false;
+
+        if let Err(error) = result {
+            // stop and leave everything up for inspection, like the SDL frontend
+            fault = Some(error.to_string());
+            paused = true;
+        }
+
+        if vm.is_halted() {
+            return Ok(fault);
+        }
+
+        bell.frame(!paused && vm.st() > 0);
+
+        // a terminal can't fade, so fading pixels stay lit until they're half gone
+        phosphor.update(vm.display());
+        let display = phosphor.display();
+        let screen = if options.braille {
+            display.to_braille()
+        } else {
+            display.to_half_blocks()
+        };
+        let status = match (&fault, paused) {
+            (Some(error), _) => format!("FAULT: {}\n{}", error, vm.get_pretty_debug_info()),
+            (None, true) => format!(
+                "PAUSED at {:03X}: {}\n{}",
+                vm.pc(),
+                disassemble_at(vm.memory(), vm.pc()),
+                vm.get_pretty_debug_info()
+            ),
+            (None, false) => String::new(),
+        };
+        if (&screen, &status) != (&last_drawn.0, &last_drawn.1) {
+            draw(&mut stdout, &screen, &status)?;
+            last_drawn = (screen, status);
+        }
+
+        if let Some(remaining) = frame_time.checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
+        }
+    }
+}
+
+fn main() {
+    let options = parse_options();
+
+    let (mut vm, instructions_per_frame) =
+        options
+            .machine
+            .load(&options.rom, None)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            });
+
+    let mut stdout = io::stdout();
+    let setup = terminal::enable_raw_mode()
+        .and_then(|_| execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All)));
+    if let Err(error) = setup {
+        eprintln!("Couldn't set up the terminal: {}", error);
+        process::exit(1);
+    }
+
+    let result = run(&mut vm, instructions_per_frame, &options);
+
+    // put the terminal back the way we found it, whatever happened
+    let _ = execute!(stdout, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+
+    match result {
+        Ok(None) => {}
+        Ok(Some(fault)) => {
+            eprintln!("VM fault: {}", fault);
+            eprintln!("{}", vm.get_pretty_debug_info());
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Terminal error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_interpreter::keymap::Keymap;
+
+    #[test]
+    fn key_names_match_sdl() {
+        assert_eq!(key_name(KeyCode::Char('q')).as_deref(), Some("q"));
+        assert_eq!(key_name(KeyCode::Char(' ')).as_deref(), Some("Space"));
+        assert_eq!(key_name(KeyCode::Enter).as_deref(), Some("Return"));
+        assert_eq!(key_name(KeyCode::Tab).as_deref(), Some("Tab"));
+        assert_eq!(key_name(KeyCode::Backspace).as_deref(), Some("Backspace"));
+        assert_eq!(key_name(KeyCode::Up).as_deref(), Some("Up"));
+        assert_eq!(key_name(KeyCode::F(1)), None);
+    }
+
+    #[test]
+    fn space_presses_what_its_bound_to() {
+        let keymap = KeymapConfig::parse("[keys]\n5 = \"Space\"\n")
+            .unwrap()
+            .keymap_for([0; 20], None);
+        let name = key_name(KeyCode::Char(' ')).unwrap();
+        assert_eq!(keymap.keys_for(&name), vec![0x5]);
+        assert_eq!(Keymap::default().keys_for(&name), Vec::<u8>::new());
+    }
+}
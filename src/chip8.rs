@@ -1,20 +1,92 @@
-use rand::Rng;
+use crate::display::{Display, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use crate::error::Chip8Error;
+use crate::hash;
+use crate::profile::{Profile, ProfileDb};
+use crate::quirks::Quirks;
+use crate::snapshot::Snapshot;
 use std::fs;
 
+// XO-CHIP gives us the full 64K a 16-bit I can address. Classic programs
+// can't reach past 4K without going through I anyway.
+pub(crate) const MEMORY_SIZE: usize = 65536;
+
+// Programs expect to be loaded here; everything below is the interpreter's.
+const PROGRAM_START: usize = 512;
+
+// Cowgod's spec gives us 16 levels of nested subroutines.
+const STACK_SIZE: usize = 16;
+
+// The SUPER-CHIP big digits are loaded right after the regular ones.
+const BIG_DIGITS_START: usize = 80;
+
+/// Whether an instruction read or wrote a byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A byte of memory read or written by an instruction. Fetching the
+/// instruction itself doesn't count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub kind: AccessKind,
+    /// The byte read, or the new one written.
+    pub value: u8,
+}
+
 pub struct Chip8 {
     registers: [u8; 16],
     memory: Vec<u8>,
     stack: Vec<u16>,
     i: u16,
-    pub dt: u8,
-    pub st: u8,
+    dt: u8,
+    st: u8,
     pc: u16,
-    display: [[u8; 64]; 32],
+    display: Display,
     keys_pressed: [bool; 16],
+    // SUPER-CHIP's Fx75/Fx85 save registers here (on the HP-48 they were the RPL flags)
+    rpl_flags: [u8; 16],
+    // set by 00FD, after which nothing else gets executed
+    halted: bool,
+    // XO-CHIP: bitmask of the display planes that drawing, clearing and scrolling affect
+    planes: u8,
+    // XO-CHIP: a 1-bit, 128 sample waveform played while the sound timer is running
+    audio_pattern: [u8; 16],
+    // set once the program loads an audio pattern, which replaces the beep
+    audio_pattern_loaded: bool,
+    // XO-CHIP: playback rate of the audio pattern; 64 is 4000 samples per second
+    pitch: u8,
+    quirks: Quirks,
+    // with the display_wait quirk, set once a sprite is drawn and cleared on vblank
+    drawn_this_frame: bool,
+    // SHA-1 of the loaded program, so save states can tell if they belong to it
+    rom_hash: [u8; 20],
+    // what the last instruction did to memory, for watchpoints
+    accesses: Vec<MemoryAccess>,
+    // what the random number generator was seeded with, and its state since
+    seed: u64,
+    rng: u64,
+    // what the profile database knows about the loaded program
+    profile: Option<Profile>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new(Quirks::default())
+    }
 }
 
 impl Chip8 {
-    pub fn new() -> Chip8 {
+    // A machine with a randomly seeded random number generator.
+    pub fn new(quirks: Quirks) -> Chip8 {
+        Chip8::with_seed(quirks, rand::random())
+    }
+
+    // A machine whose Cxkk always gives the same sequence of numbers, for
+    // reproducible runs.
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Chip8 {
         let mut x = Chip8 {
             registers: [0u8; 16],
             memory: vec![],
@@ -23,8 +95,21 @@ impl Chip8 {
             dt: 0u8,
             st: 0u8,
             pc: 512u16,
-            display: [[0u8; 64]; 32],
+            display: Display::default(),
             keys_pressed: [false; 16],
+            rpl_flags: [0u8; 16],
+            halted: false,
+            planes: 1,
+            audio_pattern: [0u8; 16],
+            audio_pattern_loaded: false,
+            pitch: 64,
+            quirks,
+            drawn_this_frame: false,
+            rom_hash: hash::sha1(&[]),
+            accesses: vec![],
+            seed,
+            rng: seed,
+            profile: None,
         };
 
         // Load the digit sprites into memory starting at 0x00. They're each 5 bytes
@@ -40,51 +125,325 @@ impl Chip8 {
 
         x.memory.append(&mut digits);
 
+        // Then the SUPER-CHIP big digits, which are 8x10 so 10 bytes each. SUPER-CHIP
+        // only had 0 through 9, but these go through F like Octo's do.
+        let mut big_digits: Vec<u8> = vec![
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18,
+            0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60,
+            0xFF, 0xFF, 0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E,
+            0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE,
+            0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, 0x3C, 0x7E, 0xC3, 0xC3,
+            0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03,
+            0x3E, 0x7C, 0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFE,
+            0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, 0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0,
+            0xC0, 0xC3, 0x7E, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0,
+            0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0,
+        ];
+
+        x.memory.append(&mut big_digits);
+
         // pad out to 512 bytes, which is where programs expect to load from
-        x.memory.append(&mut vec![0; 512 - x.memory.len()]);
+        x.memory
+            .append(&mut vec![0; PROGRAM_START - x.memory.len()]);
 
-        return x;
+        x
     }
 
-    pub fn display(&self) -> &[[u8; 64]; 32] {
-        return &self.display;
+    pub fn display(&self) -> &Display {
+        &self.display
     }
 
-    pub fn load_application(&mut self, path: &str) {
-        let mut x = match fs::read(path) {
-            Ok(file) => file,
-            Err(error) => panic!("Problem opening file! Error: {:?}", error),
-        };
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
 
-        self.memory.append(&mut x);
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // delay timer
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    // sound timer
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    pub fn keys_pressed(&self) -> &[bool; 16] {
+        &self.keys_pressed
+    }
+
+    // SHA-1 of the loaded program.
+    pub fn rom_hash(&self) -> [u8; 20] {
+        self.rom_hash
+    }
+
+    // Every byte of memory the last instruction executed read or wrote, in order.
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        &self.accesses
+    }
+
+    // True once the program has run 00FD (SUPER-CHIP exit).
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Bitmask of the selected XO-CHIP display planes.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    // True once the program has loaded an XO-CHIP audio pattern (F002), after
+    // which the pattern should be played instead of the beep.
+    pub fn audio_pattern_loaded(&self) -> bool {
+        self.audio_pattern_loaded
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // How many samples of the audio pattern to play per second, from the pitch register.
+    pub fn audio_playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // What the random number generator was seeded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // The profile for the loaded program, if load_application found one.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // The setters below are for debuggers to poke at the machine with; programs
+    // only change it by running instructions.
+
+    // register is 0-F
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.registers[register & 0xf] = value;
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.i = value;
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value;
+    }
+
+    pub fn set_dt(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    pub fn set_st(&mut self, value: u8) {
+        self.st = value;
+    }
+
+    // Make the stack this deep. Shrinking it drops the newest return addresses,
+    // and growing it pushes zeros, since there's nothing better to return to.
+    pub fn set_stack_depth(&mut self, depth: usize) -> Result<(), Chip8Error> {
+        if depth > STACK_SIZE {
+            return Err(Chip8Error::StackOverflow);
+        }
+        self.stack.resize(depth, 0);
+        Ok(())
+    }
+
+    // Unlike the program's own writes, this doesn't show up in memory_accesses.
+    pub fn poke(&mut self, addr: usize, value: u8) -> Result<(), Chip8Error> {
+        match self.memory.get_mut(addr) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(Chip8Error::MemoryOutOfBounds { addr }),
+        }
+    }
+
+    // Run one 60 Hz frame: execute instructions_per_frame instructions, then tick
+    // the timers. Stops early if the program halts or faults.
+    pub fn run_frame(&mut self, instructions_per_frame: usize) -> Result<(), Chip8Error> {
+        for _ in 0..instructions_per_frame {
+            if self.halted {
+                break;
+            }
+            self.execute_next_instruction()?;
+        }
+
+        self.tick_timers();
+
+        Ok(())
+    }
 
-        // pad out to 4K memory (ought to be enough for anybody)
+    // Count the timers down by one, which should happen at 60 Hz. run_frame does
+    // this for you. It's also the vertical blank, so with the display_wait quirk
+    // it lets the next sprite draw go ahead.
+    pub fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+        self.drawn_this_frame = false;
+    }
+
+    // Load a program from a file. If the built-in profile database knows it,
+    // this switches to the quirks it wants, and the rest of the profile is
+    // available from profile() for the frontend to use.
+    pub fn load_application(&mut self, path: &str) -> Result<(), Chip8Error> {
+        self.load_application_with(path, &ProfileDb::builtin())
+    }
+
+    // Same as load_application, but looking the program up in the given profiles.
+    pub fn load_application_with(
+        &mut self,
+        path: &str,
+        profiles: &ProfileDb,
+    ) -> Result<(), Chip8Error> {
+        let x = fs::read(path)?;
+
+        self.load_rom(&x)?;
+        self.profile = profiles.get(self.rom_hash).cloned();
+        if let Some(quirks) = self.profile.as_ref().and_then(|p| p.quirks) {
+            self.quirks = quirks;
+        }
+        Ok(())
+    }
+
+    // Same as load_application, but for a program that's already in memory.
+    // This doesn't look at the profiles, so the quirks are left alone.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        if rom.len() > MEMORY_SIZE - PROGRAM_START {
+            return Err(Chip8Error::RomTooLarge);
+        }
+
+        // throw away anything loaded previously
+        self.memory.truncate(PROGRAM_START);
+        self.memory.extend_from_slice(rom);
+        self.rom_hash = hash::sha1(rom);
+        self.profile = None;
+
+        // pad out to the full 64K
         self.memory
-            .append(&mut vec![0; (2usize).pow(12) - self.memory.len()]);
+            .append(&mut vec![0; MEMORY_SIZE - self.memory.len()]);
+
+        Ok(())
+    }
+
+    // Capture the entire machine state, to be restored later with load_state.
+    pub fn save_state(&self) -> Snapshot {
+        Snapshot {
+            rom_hash: self.rom_hash,
+            registers: self.registers,
+            memory: {
+                let mut memory = self.memory.clone();
+                memory.resize(MEMORY_SIZE, 0);
+                memory
+            },
+            stack: self.stack.clone(),
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            pc: self.pc,
+            display: self.display.clone(),
+            keys_pressed: self.keys_pressed,
+            rpl_flags: self.rpl_flags,
+            halted: self.halted,
+            drawn_this_frame: self.drawn_this_frame,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            audio_pattern_loaded: self.audio_pattern_loaded,
+            pitch: self.pitch,
+            rng: self.rng,
+        }
+    }
+
+    // Put the machine back the way it was when the snapshot was taken. The same
+    // program has to be loaded, since the snapshot doesn't carry the quirks or
+    // anything else about how it was run.
+    pub fn load_state(&mut self, snapshot: &Snapshot) -> Result<(), Chip8Error> {
+        if snapshot.rom_hash != self.rom_hash {
+            return Err(Chip8Error::SnapshotRomMismatch);
+        }
+        if snapshot.memory.len() > MEMORY_SIZE || snapshot.stack.len() > STACK_SIZE {
+            return Err(Chip8Error::InvalidSnapshot("machine state is out of range"));
+        }
+
+        self.registers = snapshot.registers;
+        self.memory.clear();
+        self.memory.extend_from_slice(&snapshot.memory);
+        self.memory.resize(MEMORY_SIZE, 0);
+        self.stack = snapshot.stack.clone();
+        self.i = snapshot.i;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        self.pc = snapshot.pc;
+        self.display = snapshot.display.clone();
+        self.keys_pressed = snapshot.keys_pressed;
+        self.rpl_flags = snapshot.rpl_flags;
+        self.halted = snapshot.halted;
+        self.drawn_this_frame = snapshot.drawn_this_frame;
+        self.planes = snapshot.planes;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.audio_pattern_loaded = snapshot.audio_pattern_loaded;
+        self.pitch = snapshot.pitch;
+        self.rng = snapshot.rng;
+
+        Ok(())
     }
 
     // Since the Chip8 keyboard is hexadecimal, we just accept a list
-    // of integers from 0x1 to 0xF, and use them to index into an arary.
+    // of integers from 0x0 to 0xF, and use them to index into an arary.
+    // Anything bigger isn't a key on the keypad, so it's ignored.
     pub fn register_keydown(&mut self, keys: impl Iterator<Item = u8>) {
         self.keys_pressed = [false; 16];
         for k in keys {
-            self.keys_pressed[k as usize] = true;
+            if let Some(pressed) = self.keys_pressed.get_mut(k as usize) {
+                *pressed = true;
+            }
         }
     }
 
     // Convert everything to nicely formatted text.
-    pub fn get_pretty_debug_info(&mut self) -> String {
+    pub fn get_pretty_debug_info(&self) -> String {
         let mut output = String::from("registers | ");
 
         output.push_str(
             &(0..=15)
-                .into_iter()
                 .map(|x| format!("{:2X}", x))
                 .collect::<Vec<String>>()
                 .join(" | "),
         );
 
-        output.push_str("\n");
+        output.push('\n');
         output.push_str("values    | ");
 
         output.push_str(
@@ -103,41 +462,105 @@ impl Chip8 {
             self.pc, self.dt, self.st
         ));
 
-        return output;
+        output
+    }
+
+    pub fn execute_next_instruction(&mut self) -> Result<(), Chip8Error> {
+        self.accesses.clear();
+        if self.halted {
+            return Ok(());
+        }
+
+        let opcode = self.fetch(self.pc as usize)?;
+
+        self.execute_opcode(opcode)?;
+
+        // We step forward after each instruction, which is why you'll see the PC
+        // stepped back by 2 in a couple places, like jumping and returning. It
+        // all wraps, so a jump to 000 comes back around to 000 here.
+        self.pc = self.pc.wrapping_add(2);
+
+        Ok(())
     }
 
-    pub fn execute_next_instruction(&mut self) {
-        // combine two bytes to get the full opcode, as a u16
-        let opcode = (self.memory[self.pc as usize] as u16) << 8
-            | self.memory[(self.pc + 1) as usize] as u16;
+    // Read the two bytes of an instruction (or of the address after F000), as a
+    // u16. This isn't a memory access as far as watchpoints are concerned.
+    fn fetch(&self, addr: usize) -> Result<u16, Chip8Error> {
+        match (self.memory.get(addr), self.memory.get(addr + 1)) {
+            (Some(high), Some(low)) => Ok((*high as u16) << 8 | *low as u16),
+            (None, _) => Err(Chip8Error::MemoryOutOfBounds { addr }),
+            (_, None) => Err(Chip8Error::MemoryOutOfBounds { addr: addr + 1 }),
+        }
+    }
 
-        self.execute_opcode(opcode);
+    // Every instruction that reads or writes memory goes through read_memory and
+    // write_memory, which keep track of what it touched.
+    fn read_memory(&mut self, addr: usize) -> Result<u8, Chip8Error> {
+        match self.memory.get(addr) {
+            Some(byte) => {
+                self.accesses.push(MemoryAccess {
+                    addr,
+                    kind: AccessKind::Read,
+                    value: *byte,
+                });
+                Ok(*byte)
+            }
+            None => Err(Chip8Error::MemoryOutOfBounds { addr }),
+        }
+    }
 
-        // We step forward after each instruction, which is why you'll see self.pc -= 2
-        // in a couple places, like jumping and returning.
-        self.pc += 2;
+    fn write_memory(&mut self, addr: usize, value: u8) -> Result<(), Chip8Error> {
+        match self.memory.get_mut(addr) {
+            Some(byte) => {
+                *byte = value;
+                self.accesses.push(MemoryAccess {
+                    addr,
+                    kind: AccessKind::Write,
+                    value,
+                });
+                Ok(())
+            }
+            None => Err(Chip8Error::MemoryOutOfBounds { addr }),
+        }
     }
 
-    fn execute_opcode(&mut self, opcode: u16) {
+    fn execute_opcode(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let unknown = Chip8Error::UnknownOpcode {
+            pc: self.pc,
+            opcode,
+        };
+
         // "first" means the most significant
         let first_nibble = (opcode >> 12) & 0xf;
         let second_nibble = ((opcode >> 8) & 0xf) as usize;
         let third_nibble = ((opcode >> 4) & 0xf) as usize;
         let last_nibble = (opcode & 0xf) as u8;
         let last_two_nibles = (opcode & 0xff) as u8;
-        let last_three_nibbles = (opcode & 0xfff) as u16;
+        let last_three_nibbles = opcode & 0xfff;
 
         match first_nibble {
             0x0 => match opcode {
                 0x00E0 => self.clear_screen(),
-                0x00EE => self.return_from_submodule(),
+                0x00EE => self.return_from_submodule()?,
+                0x00FB => self.scroll_right(),
+                0x00FC => self.scroll_left(),
+                0x00FD => self.exit(),
+                0x00FE => self.low_resolution(),
+                0x00FF => self.high_resolution(),
+                _ if opcode & 0xFFF0 == 0x00C0 => self.scroll_down(last_nibble),
+                _ if opcode & 0xFFF0 == 0x00D0 => self.scroll_up(last_nibble),
                 _ => (), // System jump, not used
             },
             0x1 => self.jump(last_three_nibbles),
-            0x2 => self.call(last_three_nibbles),
+            0x2 => self.call(last_three_nibbles)?,
             0x3 => self.skip_if_reg_equal_to_val(second_nibble, last_two_nibles),
             0x4 => self.skip_if_reg_not_equal_to_val(second_nibble, last_two_nibles),
-            0x5 => self.skip_if_reg_equal_to_reg(second_nibble, third_nibble),
+            0x5 => match last_nibble {
+                0x0 => self.skip_if_reg_equal_to_reg(second_nibble, third_nibble),
+                0x2 => self.save_register_range(second_nibble, third_nibble)?,
+                0x3 => self.load_register_range(second_nibble, third_nibble)?,
+                _ => return Err(unknown),
+            },
             0x6 => self.load_value_into_reg(second_nibble, last_two_nibles),
             0x7 => self.add_value_to_reg(second_nibble, last_two_nibles),
             0x8 => match last_nibble {
@@ -147,66 +570,129 @@ impl Chip8 {
                 0x3 => self.xor_reg(second_nibble, third_nibble),
                 0x4 => self.add_reg(second_nibble, third_nibble),
                 0x5 => self.sub_reg(second_nibble, third_nibble),
-                0x6 => self.shr_reg(second_nibble),
+                0x6 => self.shr_reg(second_nibble, third_nibble),
                 0x7 => self.subn_reg(second_nibble, third_nibble),
-                0xE => self.shl_reg(second_nibble),
-                _ => panic!("Unrecognized opcode: {:X}", opcode),
+                0xE => self.shl_reg(second_nibble, third_nibble),
+                _ => return Err(unknown),
             },
             0x9 => self.skip_next_if_reg_equal_reg(second_nibble, third_nibble),
             0xA => self.load_value_into_i(last_three_nibbles),
-            0xB => self.jump_to_reg_zero(last_three_nibbles),
+            0xB => self.jump_to_reg_zero(second_nibble, last_three_nibbles),
             0xC => self.random(second_nibble, last_two_nibles),
-            0xD => self.draw_sprite(second_nibble, third_nibble, last_nibble),
+            0xD => self.draw_sprite(second_nibble, third_nibble, last_nibble)?,
             0xE => match last_two_nibles {
                 0x9E => self.skip_next_if_key_pressed(second_nibble),
                 0xA1 => self.skip_next_if_key_not_pressed(second_nibble),
-                _ => panic!("Unrecognized opcode: {:X}", opcode),
+                _ => return Err(unknown),
             },
             0xF => match last_two_nibles {
+                0x00 if second_nibble == 0 => self.load_long_into_i()?,
+                0x01 => self.select_planes(second_nibble as u8),
+                0x02 if second_nibble == 0 => self.load_audio_pattern()?,
                 0x07 => self.load_delay_timer_into(second_nibble),
                 0x0A => self.wait_for_key(second_nibble),
                 0x15 => self.set_delay_timer_from_reg(second_nibble),
                 0x18 => self.set_sound_timer_from_reg(second_nibble),
                 0x1E => self.add_to_i(second_nibble),
                 0x29 => self.load_digit_into_i(second_nibble),
-                0x33 => self.load_bcd_of_reg_into_i(second_nibble),
-                0x55 => self.copy_registers_into_memory(second_nibble),
-                0x65 => self.read_memory_into_registers(second_nibble),
-                _ => panic!("Unrecognized opcode: {:X}", opcode),
+                0x30 => self.load_big_digit_into_i(second_nibble),
+                0x33 => self.load_bcd_of_reg_into_i(second_nibble)?,
+                0x3A => self.set_pitch_from_reg(second_nibble),
+                0x55 => self.copy_registers_into_memory(second_nibble)?,
+                0x65 => self.read_memory_into_registers(second_nibble)?,
+                0x75 => self.save_flags(second_nibble),
+                0x85 => self.load_flags(second_nibble),
+                _ => return Err(unknown),
             },
 
-            _ => panic!("Unrecognized opcode: {:X}", opcode),
+            _ => return Err(unknown),
         }
+
+        Ok(())
     }
 
     // 00E0 - CLS
+    // Only the selected planes are cleared (XO-CHIP).
     fn clear_screen(&mut self) {
-        self.display = [[0; 64]; 32];
+        self.display.clear(self.planes);
+    }
+
+    // 00Cn - SCD nibble
+    // Scroll the display down n lines.
+    fn scroll_down(&mut self, lines: u8) {
+        self.display.scroll(0, lines as isize, self.planes);
+    }
+
+    // 00Dn - SCU nibble
+    // Scroll the display up n lines (XO-CHIP).
+    fn scroll_up(&mut self, lines: u8) {
+        self.display.scroll(0, -(lines as isize), self.planes);
+    }
+
+    // 00FB - SCR
+    // Scroll the display right 4 pixels.
+    fn scroll_right(&mut self) {
+        self.display.scroll(4, 0, self.planes);
+    }
+
+    // 00FC - SCL
+    // Scroll the display left 4 pixels.
+    fn scroll_left(&mut self) {
+        self.display.scroll(-4, 0, self.planes);
+    }
+
+    // 00FD - EXIT
+    // Stop the interpreter. We just stop executing and leave it to the frontend.
+    fn exit(&mut self) {
+        self.halted = true;
+        self.pc = self.pc.wrapping_sub(2);
+    }
+
+    // 00FE - LOW
+    // Switch to 64x32 mode.
+    fn low_resolution(&mut self) {
+        self.display.resize(LORES_WIDTH, LORES_HEIGHT);
+    }
+
+    // 00FF - HIGH
+    // Switch to 128x64 mode.
+    fn high_resolution(&mut self) {
+        self.display.resize(HIRES_WIDTH, HIRES_HEIGHT);
     }
 
     // 00EE - RET
-    fn return_from_submodule(&mut self) {
-        self.pc = self.stack.pop().expect("stack was empty!");
+    fn return_from_submodule(&mut self) -> Result<(), Chip8Error> {
+        self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow)?;
+        Ok(())
     }
 
     // 1nnn - JP addr
     fn jump(&mut self, address: u16) {
-        self.pc = address;
-        self.pc -= 2
+        self.pc = address.wrapping_sub(2);
     }
 
     // 2nnn - CALL addr
-    fn call(&mut self, address: u16) {
+    fn call(&mut self, address: u16) -> Result<(), Chip8Error> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow);
+        }
         self.stack.push(self.pc);
-        self.pc = address;
-        self.pc -= 2;
+        self.pc = address.wrapping_sub(2);
+        Ok(())
+    }
+
+    // Skip over the next instruction. The XO-CHIP long load (F000 nnnn) is four
+    // bytes long, so skipping it means stepping over an extra two.
+    fn skip_next_instruction(&mut self) {
+        let is_long_load = self.fetch(self.pc as usize + 2).ok() == Some(0xF000);
+        self.pc = self.pc.wrapping_add(if is_long_load { 4 } else { 2 });
     }
 
     // 3xkk - SE Vx, byte
     // Skip next instruction if Vx = kk.
     fn skip_if_reg_equal_to_val(&mut self, register: usize, value: u8) {
         if self.registers[register] == value {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -214,7 +700,7 @@ impl Chip8 {
     // Skip next instruction if Vx != kk.
     fn skip_if_reg_not_equal_to_val(&mut self, register: usize, value: u8) {
         if self.registers[register] != value {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -222,7 +708,43 @@ impl Chip8 {
     // Skip next instruction if Vx = Vy.
     fn skip_if_reg_equal_to_reg(&mut self, register1: usize, register2: usize) {
         if self.registers[register1] == self.registers[register2] {
-            self.pc += 2;
+            self.skip_next_instruction();
+        }
+    }
+
+    // 5xy2 - SAVE Vx - Vy
+    // Store registers Vx through Vy in memory starting at location I (XO-CHIP).
+    // If x > y they're stored in reverse order. I isn't changed.
+    fn save_register_range(
+        &mut self,
+        register1: usize,
+        register2: usize,
+    ) -> Result<(), Chip8Error> {
+        for (offset, register) in Chip8::register_range(register1, register2).enumerate() {
+            self.write_memory(self.i as usize + offset, self.registers[register])?;
+        }
+        Ok(())
+    }
+
+    // 5xy3 - LOAD Vx - Vy
+    // Read registers Vx through Vy from memory starting at location I (XO-CHIP).
+    fn load_register_range(
+        &mut self,
+        register1: usize,
+        register2: usize,
+    ) -> Result<(), Chip8Error> {
+        for (offset, register) in Chip8::register_range(register1, register2).enumerate() {
+            self.registers[register] = self.read_memory(self.i as usize + offset)?;
+        }
+        Ok(())
+    }
+
+    // Registers x through y, counting down if x > y.
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
         }
     }
 
@@ -247,30 +769,40 @@ impl Chip8 {
     // 8xy1 - OR Vx, Vy
     // Set Vx = Vx OR Vy.
     fn or_reg(&mut self, register1: usize, register2: usize) {
-        self.registers[register1] = self.registers[register1] | self.registers[register2];
+        self.registers[register1] |= self.registers[register2];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xf] = 0;
+        }
     }
 
     // 8xy2 - AND Vx, Vy
     // Set Vx = Vx AND Vy.
     fn and_reg(&mut self, register1: usize, register2: usize) {
-        self.registers[register1] = self.registers[register1] & self.registers[register2];
+        self.registers[register1] &= self.registers[register2];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xf] = 0;
+        }
     }
 
     // 8xy3 - XOR Vx, Vy
     // Set Vx = Vx XOR Vy.
     fn xor_reg(&mut self, register1: usize, register2: usize) {
-        self.registers[register1] = self.registers[register1] ^ self.registers[register2];
+        self.registers[register1] ^= self.registers[register2];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xf] = 0;
+        }
     }
 
     // 8xy4 - ADD Vx, Vy
-    // Set Vx = Vx + Vy, set VF = carry.
+    // Set Vx = Vx + Vy, set VF = carry. Like the shifts, the flag is written
+    // last, so it wins when x is F.
     fn add_reg(&mut self, register1: usize, register2: usize) {
         let x = self.registers[register1];
         let y = self.registers[register2];
 
         let (result, overflow) = x.overflowing_add(y);
-        self.registers[0xf] = overflow as u8;
         self.registers[register1] = result;
+        self.registers[0xf] = overflow as u8;
     }
 
     // 8xy5 - SUB Vx, Vy
@@ -280,15 +812,22 @@ impl Chip8 {
         let y = self.registers[register2];
 
         let (result, overflow) = x.overflowing_sub(y);
-        self.registers[0xf] = !overflow as u8;
         self.registers[register1] = result;
+        self.registers[0xf] = !overflow as u8;
     }
 
     // 8xy6 - SHR Vx {, Vy}
-    // Set Vx = Vx SHR 1.
-    fn shr_reg(&mut self, register: usize) {
-        self.registers[0xf] = self.registers[register] & 0x1;
-        self.registers[register] = self.registers[register] >> 1;
+    // Set Vx = Vx SHR 1. With the shift_uses_vy quirk, Vx = Vy SHR 1.
+    fn shr_reg(&mut self, register1: usize, register2: usize) {
+        let source = if self.quirks.shift_uses_vy {
+            register2
+        } else {
+            register1
+        };
+        let value = self.registers[source];
+
+        self.registers[register1] = value >> 1;
+        self.registers[0xf] = value & 0x1;
     }
 
     // 8xy7 - SUBN Vx, Vy
@@ -298,22 +837,29 @@ impl Chip8 {
         let y = self.registers[register2];
 
         let (result, overflow) = y.overflowing_sub(x);
-        self.registers[0xf] = !overflow as u8;
         self.registers[register1] = result;
+        self.registers[0xf] = !overflow as u8;
     }
 
     // 8xyE - SHL Vx {, Vy}
-    // Set Vx = Vx SHL 1.
-    fn shl_reg(&mut self, register: usize) {
-        self.registers[0xf] = (self.registers[register] >> 7) & 0x1;
-        self.registers[register] = self.registers[register] << 1;
+    // Set Vx = Vx SHL 1. With the shift_uses_vy quirk, Vx = Vy SHL 1.
+    fn shl_reg(&mut self, register1: usize, register2: usize) {
+        let source = if self.quirks.shift_uses_vy {
+            register2
+        } else {
+            register1
+        };
+        let value = self.registers[source];
+
+        self.registers[register1] = value << 1;
+        self.registers[0xf] = (value >> 7) & 0x1;
     }
 
     // 9xy0 - SNE Vx, Vy
     // Skip next instruction if Vx != Vy.
     fn skip_next_if_reg_equal_reg(&mut self, register1: usize, register2: usize) {
         if self.registers[register1] != self.registers[register2] {
-            self.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -324,62 +870,141 @@ impl Chip8 {
     }
 
     // Bnnn - JP V0, addr
-    // Jump to location nnn + V0.
-    fn jump_to_reg_zero(&mut self, address: u16) {
-        self.pc = address + self.registers[0] as u16 - 2;
+    // Jump to location nnn + V0. With the jump_uses_vx quirk, it's nnn + Vx
+    // instead, where x is the top nibble of nnn.
+    fn jump_to_reg_zero(&mut self, register: usize, address: u16) {
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers[register]
+        } else {
+            self.registers[0]
+        };
+        self.pc = (address + offset as u16).wrapping_sub(2);
     }
 
     // Cxkk - RND Vx, byte
     // Set Vx = random byte AND kk.
     fn random(&mut self, register: usize, value: u8) {
-        self.registers[register] = rand::thread_rng().gen_range(0, 255) & value;
+        self.registers[register] = self.next_random() & value;
+    }
+
+    // SplitMix64, which is tiny and has all its state in one u64 that save
+    // states can carry. The top byte of each output is the random byte.
+    fn next_random(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
     }
 
     // Dxyn - DRW Vx, Vy, nibble
     // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-    fn draw_sprite(&mut self, xreg: usize, yreg: usize, height: u8) {
-        let x = self.registers[xreg] % 64;
-        let y = self.registers[yreg] % 32;
+    // Dxy0 draws a 16x16 sprite instead, two bytes per row (SUPER-CHIP).
+    // With both XO-CHIP planes selected, the second plane's sprite follows the first's.
+    fn draw_sprite(&mut self, xreg: usize, yreg: usize, height: u8) -> Result<(), Chip8Error> {
+        // With the display_wait quirk, only one sprite gets drawn per frame. Rewind
+        // the PC so we try again next cycle, same as waiting for a key.
+        if self.quirks.display_wait {
+            if self.drawn_this_frame {
+                self.pc = self.pc.wrapping_sub(2);
+                return Ok(());
+            }
+            self.drawn_this_frame = true;
+        }
+
+        let screen_width = self.display.width();
+        let screen_height = self.display.height();
+        let x = self.registers[xreg] as usize % screen_width;
+        let y = self.registers[yreg] as usize % screen_height;
+
+        let (sprite_width, sprite_height) = if height == 0 {
+            (16, 16)
+        } else {
+            (8, height as usize)
+        };
+        let bytes_per_row = sprite_width / 8;
 
-        // note that we clip the sprite if it goes out of bounds
+        // note that we clip the sprite if it goes out of bounds, unless the
+        // sprite_wrap quirk says to wrap it around to the other side
 
         self.registers[0xf] = 0;
 
-        for h in 0..height {
-            if (y + h) >= 32 {
-                break;
-            }
-            let b = self.memory[self.i as usize + h as usize];
-            for n in 0..8 {
-                if (x + n) >= 64 {
+        let mut sprite_address = self.i as usize;
+        let planes = self.planes;
+        for plane in [1u8, 2u8].iter().filter(|p| planes & **p != 0) {
+            for h in 0..sprite_height {
+                if (y + h) >= screen_height && !self.quirks.sprite_wrap {
                     break;
                 }
-                let pixel = (b >> (7 - n)) & 0x01;
-                if pixel > 0 {
-                    let ref mut display_pixel = self.display[(y + h) as usize][(x + n) as usize];
-                    self.registers[0xf] = *display_pixel | self.registers[0xf];
-                    *display_pixel = *display_pixel ^ pixel;
+                let mut row = 0u16;
+                for b in 0..bytes_per_row {
+                    let address = sprite_address + h * bytes_per_row + b;
+                    row = row << 8 | self.read_memory(address)? as u16;
+                }
+                for n in 0..sprite_width {
+                    if (x + n) >= screen_width && !self.quirks.sprite_wrap {
+                        break;
+                    }
+                    let pixel = (row >> (sprite_width - 1 - n)) & 0x01;
+                    if pixel > 0 {
+                        let display_pixel = self
+                            .display
+                            .pixel_mut((x + n) % screen_width, (y + h) % screen_height);
+                        if *display_pixel & plane != 0 {
+                            self.registers[0xf] = 1;
+                        }
+                        *display_pixel ^= plane;
+                    }
                 }
             }
+            sprite_address += bytes_per_row * sprite_height;
         }
+
+        Ok(())
     }
 
     // Ex9E - SKP Vx
-    // Skip next instruction if key with the value of Vx is pressed.
+    // Skip next instruction if key with the value of Vx is pressed. Only the low
+    // nibble of Vx counts, since there are only 16 keys.
     fn skip_next_if_key_pressed(&mut self, register: usize) {
-        if self.keys_pressed[self.registers[register] as usize] == true {
-            self.pc += 2;
+        if self.keys_pressed[(self.registers[register] & 0xf) as usize] {
+            self.skip_next_instruction();
         };
     }
 
     // ExA1 - SKNP Vx
-    // Skip next instruction if key with the value of Vx is not pressed.
+    // Skip next instruction if key with the value of Vx is not pressed. Again,
+    // only the low nibble of Vx counts.
     fn skip_next_if_key_not_pressed(&mut self, register: usize) {
-        if self.keys_pressed[self.registers[register] as usize] == false {
-            self.pc += 2;
+        if !self.keys_pressed[(self.registers[register] & 0xf) as usize] {
+            self.skip_next_instruction();
         };
     }
 
+    // F000 nnnn - LD I, long addr
+    // Set I = nnnn, the 16-bit word following this instruction (XO-CHIP).
+    fn load_long_into_i(&mut self) -> Result<(), Chip8Error> {
+        self.i = self.fetch(self.pc as usize + 2)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    // Fn01 - PLANE n
+    // Select which display planes drawing, clearing and scrolling affect (XO-CHIP).
+    fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0x3;
+    }
+
+    // F002 - AUDIO
+    // Load the 16 byte audio pattern from memory starting at location I (XO-CHIP).
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        for x in 0..16 {
+            self.audio_pattern[x] = self.read_memory(self.i as usize + x)?;
+        }
+        self.audio_pattern_loaded = true;
+        Ok(())
+    }
+
     // Fx07 - LD Vx, DT
     // Set Vx = delay timer value.
     fn load_delay_timer_into(&mut self, register: usize) {
@@ -390,7 +1015,7 @@ impl Chip8 {
     // Wait for a key press, store the value of the key in Vx.
     fn wait_for_key(&mut self, register: usize) {
         for (i, x) in self.keys_pressed.iter().enumerate() {
-            if x == &true {
+            if *x {
                 self.registers[register] = i as u8;
                 return;
             }
@@ -398,7 +1023,7 @@ impl Chip8 {
 
         // If we didn't find a pressed key, rewind the PC so in the next
         // cycle we hit this instruction again.
-        self.pc -= 2;
+        self.pc = self.pc.wrapping_sub(2);
     }
 
     // Fx15 - LD DT, Vx
@@ -416,47 +1041,588 @@ impl Chip8 {
     // Fx1E - ADD I, Vx
     // Set I = I + Vx.
     fn add_to_i(&mut self, register: usize) {
-        self.i += self.registers[register] as u16;
+        self.i = self.i.wrapping_add(self.registers[register] as u16);
     }
 
     // Fx29 - LD F, Vx
     // Set I = location of sprite for digit Vx.
     fn load_digit_into_i(&mut self, register: usize) {
-        self.i = (self.registers[register] * 5) as u16;
+        self.i = self.registers[register] as u16 * 5;
+    }
+
+    // Fx30 - LD HF, Vx
+    // Set I = location of the 8x10 sprite for digit Vx (SUPER-CHIP).
+    fn load_big_digit_into_i(&mut self, register: usize) {
+        self.i = BIG_DIGITS_START as u16 + self.registers[register] as u16 * 10;
     }
 
     // Fx33 - LD B, Vx
     // Store BCD representation of Vx in memory locations I, I+1, and I+2.
-    fn load_bcd_of_reg_into_i(&mut self, register: usize) {
+    fn load_bcd_of_reg_into_i(&mut self, register: usize) -> Result<(), Chip8Error> {
         let i = self.i as usize;
         let n = self.registers[register];
 
-        self.memory[i + 0] = n / 100;
-        self.memory[i + 1] = (n / 10) % 10;
-        self.memory[i + 2] = n % 10;
+        self.write_memory(i, n / 100)?;
+        self.write_memory(i + 1, (n / 10) % 10)?;
+        self.write_memory(i + 2, n % 10)
+    }
+
+    // Fx3A - PITCH Vx
+    // Set the audio pattern playback rate from Vx (XO-CHIP).
+    fn set_pitch_from_reg(&mut self, register: usize) {
+        self.pitch = self.registers[register];
     }
 
     // Fx55 - LD [I], Vx
     // Store registers V0 through Vx in memory starting at location I.
-    fn copy_registers_into_memory(&mut self, max_register: usize) {
+    fn copy_registers_into_memory(&mut self, max_register: usize) -> Result<(), Chip8Error> {
         for x in 0..=max_register {
-            self.memory[self.i as usize + x] = self.registers[x];
-            // On the original interpreter,
-            // when the operation is done, I = I + X + 1
-            // Allegedly, anyway. Copied from github.com/JamesGriffin/CHIP-8-Emulator
-            self.i += max_register as u16 + 1;
+            self.write_memory(self.i as usize + x, self.registers[x])?;
+        }
+
+        // On the original interpreter,
+        // when the operation is done, I = I + X + 1
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(max_register as u16 + 1);
         }
+
+        Ok(())
     }
 
     // Fx65 - LD Vx, [I]
     // Read registers V0 through Vx from memory starting at location I.
-    fn read_memory_into_registers(&mut self, max_register: usize) {
+    fn read_memory_into_registers(&mut self, max_register: usize) -> Result<(), Chip8Error> {
         for x in 0..=max_register {
-            self.registers[x] = self.memory[self.i as usize + x];
-            // On the original interpreter,
-            // when the operation is done, I = I + X + 1
-            // Allegedly, anyway. Copied from github.com/JamesGriffin/CHIP-8-Emulator
-            self.i += max_register as u16 + 1;
+            self.registers[x] = self.read_memory(self.i as usize + x)?;
+        }
+
+        // Same as above, I = I + X + 1 on the original interpreter
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(max_register as u16 + 1);
+        }
+
+        Ok(())
+    }
+
+    // Fx75 - LD R, Vx
+    // Store registers V0 through Vx in the RPL flags (SUPER-CHIP).
+    fn save_flags(&mut self, max_register: usize) {
+        self.rpl_flags[..=max_register].copy_from_slice(&self.registers[..=max_register]);
+    }
+
+    // Fx85 - LD Vx, R
+    // Read registers V0 through Vx from the RPL flags (SUPER-CHIP).
+    fn load_flags(&mut self, max_register: usize) {
+        self.registers[..=max_register].copy_from_slice(&self.rpl_flags[..=max_register]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A machine with the given instructions loaded at 200.
+    fn load(quirks: Quirks, program: &[u16]) -> Chip8 {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        let mut vm = Chip8::new(quirks);
+        vm.load_rom(&rom).unwrap();
+        vm
+    }
+
+    // Run every instruction in a program that doesn't jump or skip.
+    fn run(quirks: Quirks, program: &[u16]) -> Chip8 {
+        let mut vm = load(quirks, program);
+        for _ in program {
+            vm.execute_next_instruction().unwrap();
+        }
+        vm
+    }
+
+    // Run the last instruction of a program, with registers set up by the rest,
+    // and return the PC after it.
+    fn pc_after(program: &[u16]) -> u16 {
+        run(Quirks::default(), program).pc
+    }
+
+    fn v(vm: &Chip8, register: usize) -> u8 {
+        vm.registers[register]
+    }
+
+    #[test]
+    fn clear_screen() {
+        let vm = run(Quirks::default(), &[0xD005, 0x00E0]);
+        assert!(vm.display.pixels().iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut vm = load(Quirks::default(), &[0x2300]);
+        vm.poke(0x300, 0x00).unwrap();
+        vm.poke(0x301, 0xEE).unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x300);
+        assert_eq!(vm.stack, [0x200]);
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x202);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn return_with_an_empty_stack() {
+        let mut vm = load(Quirks::default(), &[0x00EE]);
+        match vm.execute_next_instruction() {
+            Err(Chip8Error::StackUnderflow) => {}
+            other => panic!("expected a stack underflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_with_a_full_stack() {
+        // calls itself forever
+        let mut vm = load(Quirks::default(), &[0x2200]);
+        for _ in 0..STACK_SIZE {
+            vm.execute_next_instruction().unwrap();
+        }
+        match vm.execute_next_instruction() {
+            Err(Chip8Error::StackOverflow) => {}
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn jump() {
+        assert_eq!(pc_after(&[0x1ABC]), 0xABC);
+    }
+
+    #[test]
+    fn jump_to_the_bottom_of_memory() {
+        assert_eq!(pc_after(&[0x1000]), 0x000);
+        assert_eq!(pc_after(&[0x1001]), 0x001);
+        assert_eq!(pc_after(&[0x6000, 0xB000]), 0x000);
+
+        let vm = run(Quirks::default(), &[0x2000]);
+        assert_eq!(vm.pc, 0x000);
+        assert_eq!(vm.stack, [0x200]);
+    }
+
+    #[test]
+    fn skip_if_equal_to_byte() {
+        assert_eq!(pc_after(&[0x6012, 0x3012]), 0x206);
+        assert_eq!(pc_after(&[0x6012, 0x3013]), 0x204);
+    }
+
+    #[test]
+    fn skip_if_not_equal_to_byte() {
+        assert_eq!(pc_after(&[0x6012, 0x4012]), 0x204);
+        assert_eq!(pc_after(&[0x6012, 0x4013]), 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_equal() {
+        assert_eq!(pc_after(&[0x6012, 0x6112, 0x5010]), 0x208);
+        assert_eq!(pc_after(&[0x6012, 0x6113, 0x5010]), 0x206);
+    }
+
+    #[test]
+    fn skip_if_registers_not_equal() {
+        assert_eq!(pc_after(&[0x6012, 0x6112, 0x9010]), 0x206);
+        assert_eq!(pc_after(&[0x6012, 0x6113, 0x9010]), 0x208);
+    }
+
+    #[test]
+    fn skipping_a_long_load_skips_both_words() {
+        let mut vm = load(Quirks::default(), &[0x3000, 0xF000, 0x1234]);
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x206);
+    }
+
+    #[test]
+    fn load_and_add_byte() {
+        let vm = run(Quirks::default(), &[0x6A12, 0x7A34]);
+        assert_eq!(v(&vm, 0xA), 0x46);
+    }
+
+    #[test]
+    fn add_byte_wraps_without_touching_vf() {
+        let vm = run(Quirks::default(), &[0x60FF, 0x7002]);
+        assert_eq!(v(&vm, 0), 0x01);
+        assert_eq!(v(&vm, 0xF), 0);
+    }
+
+    #[test]
+    fn load_register() {
+        let vm = run(Quirks::default(), &[0x6142, 0x8010]);
+        assert_eq!(v(&vm, 0), 0x42);
+    }
+
+    #[test]
+    fn logic() {
+        let setup = [0x60F0, 0x613C, 0x6F07];
+        for (op, expected) in [(0x8011, 0xFC), (0x8012, 0x30), (0x8013, 0xCC)] {
+            let vm = run(Quirks::default(), &[setup[0], setup[1], setup[2], op]);
+            assert_eq!(v(&vm, 0), expected);
+            assert_eq!(v(&vm, 0xF), 7);
+
+            let vm = run(Quirks::COSMAC_VIP, &[setup[0], setup[1], setup[2], op]);
+            assert_eq!(v(&vm, 0), expected);
+            assert_eq!(v(&vm, 0xF), 0);
+        }
+    }
+
+    #[test]
+    fn add_registers_sets_carry() {
+        let vm = run(Quirks::default(), &[0x60F0, 0x6120, 0x8014]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x10, 1));
+        let vm = run(Quirks::default(), &[0x6010, 0x6120, 0x8014]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x30, 0));
+    }
+
+    #[test]
+    fn subtract_registers_sets_not_borrow() {
+        let vm = run(Quirks::default(), &[0x6030, 0x6110, 0x8015]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x20, 1));
+        let vm = run(Quirks::default(), &[0x6010, 0x6130, 0x8015]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0xE0, 0));
+        // equal isn't a borrow
+        let vm = run(Quirks::default(), &[0x6010, 0x6110, 0x8015]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x00, 1));
+    }
+
+    #[test]
+    fn reverse_subtract_registers_sets_not_borrow() {
+        let vm = run(Quirks::default(), &[0x6010, 0x6130, 0x8017]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x20, 1));
+        let vm = run(Quirks::default(), &[0x6030, 0x6110, 0x8017]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0xE0, 0));
+    }
+
+    #[test]
+    fn arithmetic_on_vf_keeps_the_flag() {
+        let vm = run(Quirks::default(), &[0x6FF0, 0x6120, 0x8F14]);
+        assert_eq!(v(&vm, 0xF), 1);
+        let vm = run(Quirks::default(), &[0x6F10, 0x6120, 0x8F15]);
+        assert_eq!(v(&vm, 0xF), 0);
+        let vm = run(Quirks::default(), &[0x6F10, 0x6120, 0x8F17]);
+        assert_eq!(v(&vm, 0xF), 1);
+    }
+
+    #[test]
+    fn shift_right() {
+        let vm = run(Quirks::default(), &[0x6005, 0x6140, 0x8016]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x02, 1));
+        let vm = run(Quirks::COSMAC_VIP, &[0x6005, 0x6140, 0x8016]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x20, 0));
+    }
+
+    #[test]
+    fn shift_left() {
+        let vm = run(Quirks::default(), &[0x6081, 0x6101, 0x801E]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x02, 1));
+        let vm = run(Quirks::COSMAC_VIP, &[0x6081, 0x6101, 0x801E]);
+        assert_eq!((v(&vm, 0), v(&vm, 0xF)), (0x02, 0));
+    }
+
+    #[test]
+    fn load_i() {
+        let vm = run(Quirks::default(), &[0xA123]);
+        assert_eq!(vm.i, 0x123);
+    }
+
+    #[test]
+    fn jump_with_offset() {
+        let program = [0x6004, 0x6308, 0xB300];
+        assert_eq!(run(Quirks::default(), &program).pc, 0x304);
+        assert_eq!(run(Quirks::CHIP_48, &program).pc, 0x308);
+    }
+
+    #[test]
+    fn random_is_repeatable_with_a_seed() {
+        let numbers = |seed| {
+            let mut vm = Chip8::with_seed(Quirks::default(), seed);
+            (0..32).map(|_| vm.next_random()).collect::<Vec<u8>>()
+        };
+        assert_eq!(numbers(42), numbers(42));
+        assert_ne!(numbers(42), numbers(43));
+    }
+
+    #[test]
+    fn random_covers_every_byte() {
+        let mut vm = Chip8::with_seed(Quirks::default(), 0);
+        let mut seen = [false; 256];
+        for _ in 0..10_000 {
+            seen[vm.next_random() as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn random_state_is_saved() {
+        let mut vm = load(Quirks::default(), &[0xC0FF, 0xC0FF]);
+        let snapshot = vm.save_state();
+        vm.execute_next_instruction().unwrap();
+        let first = v(&vm, 0);
+
+        vm.load_state(&Snapshot::from_bytes(&snapshot.to_bytes()).unwrap())
+            .unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(v(&vm, 0), first);
+    }
+
+    #[test]
+    fn random_is_masked() {
+        for _ in 0..100 {
+            let vm = run(Quirks::default(), &[0xC00F, 0xC100]);
+            assert!(v(&vm, 0) <= 0x0F);
+            assert_eq!(v(&vm, 1), 0);
+        }
+    }
+
+    #[test]
+    fn draw_sets_collision() {
+        // the font's 0, at (1, 2), then again to erase it
+        let vm = run(Quirks::default(), &[0x6001, 0x6102, 0xD015]);
+        assert_eq!(vm.display.get(1, 2), 1);
+        assert_eq!(vm.display.get(5, 2), 0);
+        assert_eq!(vm.display.get(2, 3), 0);
+        assert_eq!(v(&vm, 0xF), 0);
+
+        let vm = run(Quirks::default(), &[0x6001, 0x6102, 0xD015, 0xD015]);
+        assert!(vm.display.pixels().iter().all(|pixel| *pixel == 0));
+        assert_eq!(v(&vm, 0xF), 1);
+    }
+
+    #[test]
+    fn draw_clips_or_wraps_at_the_edges() {
+        let program = [0x603E, 0x611E, 0xD015];
+        let vm = run(Quirks::default(), &program);
+        assert_eq!(vm.display.get(62, 30), 1);
+        assert_eq!(vm.display.get(0, 30), 0);
+        assert_eq!(vm.display.get(62, 0), 0);
+
+        let vm = run(Quirks::OCTO, &program);
+        assert_eq!(vm.display.get(62, 30), 1);
+        assert_eq!(vm.display.get(0, 30), 1);
+        assert_eq!(vm.display.get(62, 0), 1);
+    }
+
+    #[test]
+    fn draw_position_wraps() {
+        // x = 65 starts drawing at 1, even without sprite wrapping
+        let vm = run(Quirks::default(), &[0x6041, 0xD015]);
+        assert_eq!(vm.display.get(1, 0), 1);
+    }
+
+    #[test]
+    fn draw_waits_for_vblank() {
+        let mut vm = load(Quirks::COSMAC_VIP, &[0xD015, 0xD015]);
+        vm.execute_next_instruction().unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x202);
+        vm.tick_timers();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x204);
+    }
+
+    #[test]
+    fn draw_big_sprites_in_hires() {
+        let vm = run(Quirks::default(), &[0x00FF, 0xA000, 0xD000]);
+        assert!(vm.display.is_hires());
+        // 16x16 from the first 32 bytes of the font
+        assert_eq!(vm.display.get(0, 0), 1);
+        assert_eq!(vm.display.get(8, 0), 1);
+        assert_eq!(vm.display.get(9, 0), 0);
+        assert_eq!(vm.display.get(8, 15), 1);
+        assert_eq!(vm.display.get(12, 15), 0);
+    }
+
+    #[test]
+    fn skip_on_keys() {
+        let mut vm = load(Quirks::default(), &[0x6005, 0xE09E]);
+        vm.register_keydown(vec![5].into_iter());
+        vm.execute_next_instruction().unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x206);
+
+        assert_eq!(pc_after(&[0x6005, 0xE09E]), 0x204);
+        assert_eq!(pc_after(&[0x6005, 0xE0A1]), 0x206);
+    }
+
+    #[test]
+    fn skip_on_keys_above_f() {
+        // only the low nibble picks the key, so 25 is key 5
+        let mut vm = load(Quirks::default(), &[0x6025, 0xE09E]);
+        vm.register_keydown(vec![5].into_iter());
+        vm.execute_next_instruction().unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x206);
+
+        assert_eq!(pc_after(&[0x6020, 0xE09E]), 0x204);
+        assert_eq!(pc_after(&[0x60FF, 0xE0A1]), 0x206);
+    }
+
+    #[test]
+    fn keys_off_the_keypad_are_ignored() {
+        let mut vm = load(Quirks::default(), &[0x6005, 0xE09E]);
+        vm.register_keydown(vec![16, 5, 255].into_iter());
+        assert_eq!(vm.keys_pressed.iter().filter(|key| **key).count(), 1);
+        vm.execute_next_instruction().unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x206);
+    }
+
+    #[test]
+    fn wait_for_key() {
+        let mut vm = load(Quirks::default(), &[0xF30A]);
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x200);
+        vm.register_keydown(vec![0xB].into_iter());
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.pc, 0x202);
+        assert_eq!(v(&vm, 3), 0xB);
+    }
+
+    #[test]
+    fn timers() {
+        let mut vm = run(Quirks::default(), &[0x6003, 0xF015, 0xF018]);
+        assert_eq!((vm.dt, vm.st), (3, 3));
+        vm.tick_timers();
+        assert_eq!((vm.dt, vm.st), (2, 2));
+
+        let vm = run(Quirks::default(), &[0x6009, 0xF015, 0xF107]);
+        assert_eq!(v(&vm, 1), 9);
+    }
+
+    #[test]
+    fn add_to_i_wraps_at_16_bits() {
+        let vm = run(Quirks::default(), &[0xF000, 0xFFFF, 0x6002, 0xF01E]);
+        assert_eq!(vm.i, 0x0001);
+    }
+
+    #[test]
+    fn digit_sprites() {
+        let vm = run(Quirks::default(), &[0x600A, 0xF029]);
+        assert_eq!(vm.i, 50);
+        let vm = run(Quirks::default(), &[0x6003, 0xF030]);
+        assert_eq!(vm.i, BIG_DIGITS_START as u16 + 30);
+    }
+
+    #[test]
+    fn binary_coded_decimal() {
+        let vm = run(Quirks::default(), &[0x60FE, 0xA300, 0xF033]);
+        assert_eq!(vm.memory[0x300..0x303], [2, 5, 4]);
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let vm = run(Quirks::default(), &[0x6011, 0x6122, 0x6233, 0xA300, 0xF155]);
+        assert_eq!(vm.memory[0x300..0x303], [0x11, 0x22, 0x00]);
+        assert_eq!(vm.i, 0x302);
+
+        let vm = run(Quirks::CHIP_48, &[0xA000, 0xF165]);
+        assert_eq!((v(&vm, 0), v(&vm, 1), v(&vm, 2)), (0xF0, 0x90, 0));
+        assert_eq!(vm.i, 0);
+    }
+
+    #[test]
+    fn store_and_load_wrap_i_at_16_bits() {
+        let vm = run(Quirks::default(), &[0xF000, 0xFFF0, 0xFF55]);
+        assert_eq!(vm.memory[0xFFF0..], vm.registers);
+        assert_eq!(vm.i, 0x0000);
+        let vm = run(Quirks::default(), &[0xF000, 0xFFF0, 0xFF65]);
+        assert_eq!(vm.i, 0x0000);
+    }
+
+    #[test]
+    fn store_out_of_bounds() {
+        let mut vm = load(Quirks::default(), &[0xF000, 0xFFFF, 0xF155]);
+        vm.execute_next_instruction().unwrap();
+        match vm.execute_next_instruction() {
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x10000 }) => {}
+            other => panic!("expected an out of bounds write, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn flags() {
+        let vm = run(
+            Quirks::default(),
+            &[0x6042, 0x6117, 0xF175, 0x6000, 0x6100, 0xF185],
+        );
+        assert_eq!((v(&vm, 0), v(&vm, 1)), (0x42, 0x17));
+    }
+
+    #[test]
+    fn scrolling() {
+        // a pixel at (8, 8), then scrolled down 3, up 1, right 4 and left 4
+        let program = [0x6008, 0xA000, 0xD001, 0x00C3, 0x00D1];
+        let vm = run(Quirks::default(), &program);
+        assert_eq!(vm.display.get(8, 10), 1);
+
+        let vm = run(Quirks::default(), &[0x6008, 0xA000, 0xD001, 0x00FB]);
+        assert_eq!(vm.display.get(12, 8), 1);
+        let vm = run(Quirks::default(), &[0x6008, 0xA000, 0xD001, 0x00FC]);
+        assert_eq!(vm.display.get(4, 8), 1);
+    }
+
+    #[test]
+    fn resolution() {
+        let vm = run(Quirks::default(), &[0x00FF]);
+        assert_eq!((vm.display.width(), vm.display.height()), (128, 64));
+        let vm = run(Quirks::default(), &[0x00FF, 0x00FE]);
+        assert_eq!((vm.display.width(), vm.display.height()), (64, 32));
+    }
+
+    #[test]
+    fn exit_halts() {
+        let mut vm = load(Quirks::default(), &[0x00FD, 0x6001]);
+        vm.execute_next_instruction().unwrap();
+        vm.execute_next_instruction().unwrap();
+        assert!(vm.halted);
+        assert_eq!(vm.pc, 0x200);
+        assert_eq!(v(&vm, 0), 0);
+    }
+
+    #[test]
+    fn register_ranges() {
+        let vm = run(
+            Quirks::default(),
+            &[0x6111, 0x6222, 0x6333, 0xA300, 0x5132, 0xA310, 0x5312],
+        );
+        assert_eq!(vm.memory[0x300..0x303], [0x11, 0x22, 0x33]);
+        assert_eq!(vm.memory[0x310..0x313], [0x33, 0x22, 0x11]);
+        assert_eq!(vm.i, 0x310);
+
+        let vm = run(Quirks::default(), &[0xA000, 0x5743]);
+        // backwards, from V7 down to V4
+        assert_eq!((v(&vm, 7), v(&vm, 6), v(&vm, 5)), (0xF0, 0x90, 0x90));
+        assert_eq!(v(&vm, 4), 0x90);
+    }
+
+    #[test]
+    fn long_load() {
+        let mut vm = load(Quirks::default(), &[0xF000, 0xABCD]);
+        vm.execute_next_instruction().unwrap();
+        assert_eq!(vm.i, 0xABCD);
+        assert_eq!(vm.pc, 0x204);
+    }
+
+    #[test]
+    fn planes_audio_and_pitch() {
+        let vm = run(Quirks::default(), &[0xF301, 0xA000, 0xF002, 0x6070, 0xF03A]);
+        assert_eq!(vm.planes, 3);
+        assert_eq!(vm.audio_pattern[..5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert!(vm.audio_pattern_loaded);
+        assert_eq!(vm.pitch, 0x70);
+    }
+
+    #[test]
+    fn unknown_opcodes() {
+        for opcode in [0x5001, 0x8008, 0xE000, 0xF0FF] {
+            let mut vm = load(Quirks::default(), &[opcode]);
+            match vm.execute_next_instruction() {
+                Err(Chip8Error::UnknownOpcode { pc: 0x200, .. }) => {}
+                other => panic!("expected {:04X} to be unknown, got {:?}", opcode, other),
+            }
         }
     }
 }
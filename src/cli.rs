@@ -0,0 +1,417 @@
+// Command line handling shared by the frontends: reading flags and their values,
+// and the options they have in common for picking the machine to run (quirks,
+// speed, seed and profiles), tracing it, recording or playing back movies, and
+// running it without a window.
+//
+// Anything wrong on the command line is a usage error, which prints what's wrong
+// and the usage, then exits with status 2. That's no way for a library to behave,
+// so this is a helper for the binaries in this crate rather than part of the
+// library's API, and lib.rs hides it from the docs.
+
+use crate::audio::{AudioSink, Tone, WavSink};
+use crate::movie::Movie;
+use crate::profile::ProfileDb;
+use crate::script::KeyScript;
+use crate::trace::{self, Tracer};
+use crate::{Chip8, Quirks};
+
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::iter::Skip;
+use std::process;
+use std::str::FromStr;
+
+/// The arguments after the program name, along with the usage to show when
+/// they're wrong.
+pub struct Args {
+    args: Skip<env::Args>,
+    usage: String,
+}
+
+impl Args {
+    pub fn new(usage: String) -> Args {
+        Args {
+            args: env::args().skip(1),
+            usage,
+        }
+    }
+
+    /// Print the message and the usage, and exit.
+    pub fn error(&self, message: &str) -> ! {
+        eprintln!("{}\n\n{}", message, self.usage);
+        process::exit(2);
+    }
+
+    /// Print the usage and exit, for `--help`.
+    pub fn help(&self) -> ! {
+        println!("{}", self.usage);
+        process::exit(0);
+    }
+
+    /// The value given for a flag, or a usage error if it's missing.
+    pub fn value(&mut self, flag: &str) -> String {
+        match self.args.next() {
+            Some(value) => value,
+            None => self.error(&format!("Missing value for {}", flag)),
+        }
+    }
+
+    /// The value given for a flag, parsed, or a usage error if it doesn't parse.
+    pub fn parse<T: FromStr>(&mut self, flag: &str) -> T {
+        let value = self.value(flag);
+        match value.parse() {
+            Ok(parsed) => parsed,
+            Err(_) => self.error(&format!("Invalid value '{}' for {}", value, flag)),
+        }
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.args.next()
+    }
+}
+
+/// `--ipf`, `--quirks`, `--profiles` and `--seed`, which every frontend that
+/// runs a program takes.
+pub struct MachineOptions {
+    pub instructions_per_frame: Option<usize>,
+    pub quirks: Option<Quirks>,
+    pub profiles: ProfileDb,
+    pub seed: Option<u64>,
+}
+
+impl Default for MachineOptions {
+    fn default() -> MachineOptions {
+        MachineOptions {
+            instructions_per_frame: None,
+            quirks: None,
+            profiles: ProfileDb::builtin(),
+            seed: None,
+        }
+    }
+}
+
+impl MachineOptions {
+    pub const USAGE: &'static str =
+        "  --ipf <n>          instructions per frame (default 8, about 500 hz, unless
+                     the program's profile says otherwise)
+  --quirks <preset>  vip, chip48, schip or octo, instead of the profile's
+  --profiles <file>  more program profiles, in chip-8-database JSON or TOML
+  --seed <n>         seed the random number generator, for reproducible runs";
+
+    /// Take the flag (and its value) if it's one of ours. Returns false if it
+    /// isn't.
+    pub fn parse(&mut self, flag: &str, args: &mut Args) -> bool {
+        match flag {
+            "--ipf" => self.instructions_per_frame = Some(args.parse(flag)),
+            "--quirks" => {
+                let name = args.value(flag);
+                self.quirks = Some(Quirks::from_name(&name).unwrap_or_else(|| {
+                    let names: Vec<&str> = Quirks::PRESETS.iter().map(|(n, _)| *n).collect();
+                    args.error(&format!(
+                        "Unknown quirks preset '{}'. Choose one of: {}",
+                        name,
+                        names.join(", ")
+                    ))
+                }));
+            }
+            "--profiles" => {
+                let path = args.value(flag);
+                let profiles = ProfileDb::load(&path).unwrap_or_else(|error| {
+                    args.error(&format!("Couldn't load {}: {}", path, error))
+                });
+                self.profiles.merge(profiles);
+            }
+            "--seed" => self.seed = Some(args.parse(flag)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Create the machine and load the program into it, returning it and how
+    /// many instructions to run a frame. A movie being played back decides the
+    /// seed, quirks and speed, then anything on the command line beats what the
+    /// program's profile says.
+    pub fn load(&self, rom: &str, play: Option<&Movie>) -> Result<(Chip8, usize), String> {
+        let mut vm = match (play, self.seed) {
+            (Some(movie), _) => movie.machine(),
+            (None, Some(seed)) => Chip8::with_seed(Quirks::default(), seed),
+            (None, None) => Chip8::new(Quirks::default()),
+        };
+        vm.load_application_with(rom, &self.profiles)
+            .map_err(|error| format!("Couldn't load {}: {}", rom, error))?;
+
+        if let Some(quirks) = play.map(Movie::quirks).or(self.quirks) {
+            vm.set_quirks(quirks);
+        }
+        let instructions_per_frame = play
+            .map(Movie::instructions_per_frame)
+            .or(self.instructions_per_frame)
+            .or_else(|| vm.profile().and_then(|profile| profile.tickrate))
+            .unwrap_or(8);
+        if let Some(movie) = play {
+            movie
+                .check_rom(&vm)
+                .map_err(|error| format!("Can't play the movie: {}", error))?;
+        }
+        Ok((vm, instructions_per_frame))
+    }
+}
+
+/// `--trace`, `--trace-range`, `--trace-last`, `--record` and `--play`, for
+/// the frontends that can keep a record of a run.
+#[derive(Default)]
+pub struct SessionOptions {
+    pub trace: Option<String>,
+    pub trace_range: Option<(u16, u16)>,
+    pub trace_last: Option<usize>,
+    pub record: Option<String>,
+    pub play: Option<Movie>,
+}
+
+impl SessionOptions {
+    pub const USAGE: &'static str = "  --trace <file>     log every instruction executed to a file
+  --trace-range <start>-<end>
+                     only trace instructions in this (hex) range of addresses
+  --trace-last <n>   only write out the last n instructions, if it faults
+  --record <file>    record the keys held on every frame to a movie
+  --play <file>      play back a movie, checking it doesn't desync; this
+                     sets the seed, quirks and speed";
+
+    /// Take the flag (and its value) if it's one of ours. Returns false if it
+    /// isn't.
+    pub fn parse(&mut self, flag: &str, args: &mut Args) -> bool {
+        match flag {
+            "--trace" => self.trace = Some(args.value(flag)),
+            "--trace-range" => {
+                let range = args.value(flag);
+                self.trace_range = Some(trace::parse_range(&range).unwrap_or_else(|| {
+                    args.error(&format!(
+                        "Invalid range '{}' for --trace-range, e.g. 200-2FF",
+                        range
+                    ))
+                }));
+            }
+            "--trace-last" => self.trace_last = Some(args.parse(flag)),
+            "--record" => self.record = Some(args.value(flag)),
+            "--play" => {
+                let path = args.value(flag);
+                self.play = Some(Movie::load(&path).unwrap_or_else(|error| {
+                    args.error(&format!("Couldn't load {}: {}", path, error))
+                }));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// A movie decides how it plays out, so it's a usage error to try and
+    /// change that from the command line, whatever order the flags came in.
+    pub fn check(&self, machine: &MachineOptions, args: &Args) {
+        if self.play.is_some()
+            && (machine.instructions_per_frame.is_some()
+                || machine.quirks.is_some()
+                || machine.seed.is_some())
+        {
+            args.error("--ipf, --quirks and --seed come from the movie with --play");
+        }
+    }
+
+    /// The tracer `--trace` asked for, if it did.
+    pub fn tracer(&self) -> Result<Option<Tracer>, String> {
+        let path = match &self.trace {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let file =
+            File::create(path).map_err(|error| format!("Couldn't create {}: {}", path, error))?;
+        let mut tracer = Tracer::new(Box::new(BufWriter::new(file)));
+        if let Some((start, end)) = self.trace_range {
+            tracer = tracer.only_between(start, end);
+        }
+        if let Some(count) = self.trace_last {
+            tracer = tracer.ring_buffer(count);
+        }
+        Ok(Some(tracer))
+    }
+}
+
+/// `--frames`, `--keys`, `--format`, `--screen` and `--wav`, for running a
+/// program without a window: `chip8-headless`, or the emulator's `--headless`.
+#[derive(Default)]
+pub struct HeadlessOptions {
+    pub frames: Option<u64>,
+    pub keys: KeyScript,
+    pub pbm: bool,
+    pub screen: Option<String>,
+    pub wav: Option<String>,
+}
+
+impl HeadlessOptions {
+    pub const USAGE: &'static str =
+        "  --frames <n>       run for n frames at 60 fps (default 600, or the length
+                     of the movie with --play)
+  --keys <file>      scripted key presses, lines of `<frame> <hex keys...>`
+  --format <fmt>     dump the screen as ascii (default) or pbm
+  --screen <file>    write the screen to a file instead of standard out
+  --wav <file>       render the beeper to a WAV file";
+
+    /// Take the flag (and its value) if it's one of ours. Returns false if it
+    /// isn't.
+    pub fn parse(&mut self, flag: &str, args: &mut Args) -> bool {
+        match flag {
+            "--frames" => self.frames = Some(args.parse(flag)),
+            "--keys" => {
+                let path = args.value(flag);
+                let text = fs::read_to_string(&path).unwrap_or_else(|error| {
+                    args.error(&format!("Couldn't read {}: {}", path, error))
+                });
+                self.keys = KeyScript::parse(&text)
+                    .unwrap_or_else(|error| args.error(&format!("{}: {}", path, error)));
+            }
+            "--format" => match args.value(flag).as_str() {
+                "ascii" => self.pbm = false,
+                "pbm" => self.pbm = true,
+                _ => args.error("--format must be ascii or pbm"),
+            },
+            "--screen" => self.screen = Some(args.value(flag)),
+            "--wav" => self.wav = Some(args.value(flag)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// A movie runs for as long as it was recorded.
+    pub fn check(&self, session: &SessionOptions, args: &Args) {
+        if session.play.is_some() && self.frames.is_some() {
+            args.error("--frames comes from the movie with --play");
+        }
+    }
+
+    /// Run the program as fast as it'll go, for a number of frames or until it
+    /// halts or faults, then print the screen and a summary of the machine,
+    /// and exit: with status 1 if it faulted or a movie desynced.
+    pub fn run(&self, rom: &str, machine: &MachineOptions, session: &SessionOptions) -> ! {
+        let play = session.play.as_ref();
+        let (mut vm, instructions_per_frame) = machine.load(rom, play).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        let mut recording = session
+            .record
+            .as_ref()
+            .map(|_| Movie::new(&vm, instructions_per_frame));
+
+        let mut wav = self
+            .wav
+            .as_ref()
+            .map(|_| WavSink::new(Tone::default(), 44_100));
+
+        let mut tracer = session.tracer().unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+
+        let frames = self
+            .frames
+            .or_else(|| play.map(|movie| movie.len() as u64))
+            .unwrap_or(600);
+        let mut frames_run = 0;
+        let mut fault = None;
+        let mut desync = None;
+        while frames_run < frames && !vm.is_halted() {
+            match play {
+                Some(movie) => {
+                    vm.register_keydown(movie.keys_at(frames_run).unwrap_or_default().into_iter())
+                }
+                None => vm.register_keydown(self.keys.keys_at(frames_run).iter().cloned()),
+            }
+            let result = match tracer.as_mut() {
+                Some(tracer) => tracer.run_frame(&mut vm, instructions_per_frame),
+                None => vm.run_frame(instructions_per_frame),
+            };
+            frames_run += 1;
+
+            if let Some(wav) = wav.as_mut() {
+                if vm.audio_pattern_loaded() {
+                    wav.pattern(vm.audio_pattern(), vm.audio_playback_rate());
+                }
+                wav.frame(vm.st() > 0);
+            }
+
+            if let Err(error) = result {
+                fault = Some(error);
+                break;
+            }
+
+            if let Some(movie) = recording.as_mut() {
+                movie.record(&vm);
+            }
+            if let Some(Err(error)) = play.map(|movie| movie.verify(frames_run - 1, &vm)) {
+                desync = Some(error);
+                break;
+            }
+        }
+
+        // flush the trace, since exiting doesn't drop it
+        drop(tracer);
+
+        let screen = if self.pbm {
+            vm.display().to_pbm()
+        } else {
+            vm.display().to_ascii()
+        };
+        match &self.screen {
+            Some(path) => write_or_exit(path, fs::write(path, screen)),
+            None => print!("{}", screen),
+        }
+        if let (Some(movie), Some(path)) = (recording, &session.record) {
+            write_or_exit(path, movie.save(path));
+        }
+        if let (Some(wav), Some(path)) = (wav, &self.wav) {
+            write_or_exit(path, wav.save(path));
+        }
+
+        let outcome = match (&fault, &desync) {
+            (Some(error), _) => format!("faulted: {}", error),
+            (None, Some(error)) => error.clone(),
+            (None, None) if vm.is_halted() => String::from("halted"),
+            (None, None) => String::from("ok"),
+        };
+        println!("\nRan {} frames, {}", frames_run, outcome);
+        println!("{}", vm.get_pretty_debug_info());
+        println!("Stack: {:x?}", vm.stack());
+        println!("Seed: {}", vm.seed());
+        println!("Memory at PC {}", hexdump(&vm, vm.pc()));
+        println!("Memory at I  {}", hexdump(&vm, vm.i()));
+
+        process::exit(if fault.is_some() || desync.is_some() {
+            1
+        } else {
+            0
+        });
+    }
+}
+
+fn write_or_exit<E: Display>(path: &str, result: Result<(), E>) {
+    if let Err(error) = result {
+        eprintln!("Couldn't write {}: {}", path, error);
+        process::exit(1);
+    }
+}
+
+// 16 bytes of memory starting at address, as hex.
+fn hexdump(vm: &Chip8, address: u16) -> String {
+    let start = address as usize;
+    let end = (start + 16).min(vm.memory().len());
+    let bytes: Vec<String> = vm.memory()[start.min(end)..end]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{:04x}: {}", address, bytes.join(" "))
+}
@@ -0,0 +1,533 @@
+// An interactive debugger, driven by commands typed at a prompt. The frontend
+// hands it each line the user types, and while it's running lets it execute
+// frames so it can stop at breakpoints and watchpoints. Numbers are all hex,
+// like addresses in the disassembly.
+
+use crate::chip8::{AccessKind, MemoryAccess};
+use crate::disasm::disassemble_at;
+use crate::trace::Tracer;
+use crate::{Chip8, Chip8Error};
+
+use std::fmt;
+
+pub const HELP: &str = "commands (numbers are hex):
+  break <addr> [if <reg> <op> <value>]  stop at addr, optionally only when
+                                        V0-VF or I compares true (== != < <= > >=)
+  delete [<addr>]                       remove the breakpoints at addr, or all of them
+  watch <addr> [<len>]                  stop when the program writes to memory
+  rwatch <addr> [<len>]                 ... or reads from it
+  awatch <addr> [<len>]                 ... or does either
+  unwatch [<addr>]                      remove the watchpoints covering addr, or all
+  breakpoints                           list breakpoints and watchpoints
+  continue                              run until a breakpoint
+  step [<n>]                            execute one (or n) instructions
+  next                                  step, running through any subroutine call
+  finish                                run until the current subroutine returns
+  until <addr>                          run until PC reaches addr
+  regs                                  show registers, timers and the stack
+  set <reg> <value>                     change V0-VF, I or PC
+  dump [<addr>] [<len>]                 show memory, from I by default
+  poke <addr> <byte>...                 write bytes to memory
+  dis [<addr>] [<n>]                    disassemble, from PC by default
+  quit                                  exit the emulator
+An empty line repeats the last command.";
+
+/// What the frontend should do after a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Stay paused and wait for the next command.
+    Stay,
+    /// Run the program, calling [`Debugger::run_frame`] each frame.
+    Resume,
+    Quit,
+}
+
+/// What a conditional breakpoint looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(usize),
+    I,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Longest first, so <= isn't taken for <
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub location: Location,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    /// Parse a condition like `V3 == 1F` or `I>=300`.
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let (operator, comparison) = COMPARISONS
+            .iter()
+            .find(|(operator, _)| text.contains(operator))
+            .ok_or_else(|| format!("No comparison in '{}'", text))?;
+        let mut sides = text.splitn(2, operator);
+        let location = parse_location(sides.next().unwrap_or(""))?;
+        let value = parse_number(sides.next().unwrap_or(""))?;
+
+        Ok(Condition {
+            location,
+            comparison: *comparison,
+            value,
+        })
+    }
+
+    pub fn holds(&self, vm: &Chip8) -> bool {
+        let actual = match self.location {
+            Location::Register(register) => vm.registers()[register] as u16,
+            Location::I => vm.i(),
+        };
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = COMPARISONS
+            .iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .map(|(operator, _)| *operator)
+            .unwrap_or("?");
+        match self.location {
+            Location::Register(register) => write!(f, "V{:X}", register)?,
+            Location::I => write!(f, "I")?,
+        }
+        write!(f, " {} {:X}", operator, self.value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    /// Only stop if this holds when we get to the address.
+    pub condition: Option<Condition>,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}", self.address)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// A range of memory to stop on accesses to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    /// Inclusive, so a single byte has the same start and end.
+    pub end: usize,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind = match access.kind {
+            AccessKind::Read => self.on_read,
+            AccessKind::Write => self.on_write,
+        };
+        kind && (self.start..=self.end).contains(&access.addr)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}", self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:03X}", self.end)?;
+        }
+        match (self.on_read, self.on_write) {
+            (true, true) => write!(f, " (read/write)"),
+            (true, false) => write!(f, " (read)"),
+            _ => write!(f, " (write)"),
+        }
+    }
+}
+
+// Where next, finish and until are running to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Address(u16),
+    // until the stack is no deeper than this
+    StackDepth(usize),
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    target: Option<Target>,
+    // set on resuming, so we don't stop straight away at the breakpoint we're on
+    resuming: bool,
+    last_command: String,
+    tracer: Option<Tracer>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Trace every instruction the debugger runs, stepping or not.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Run one command, returning what to print and what to do next.
+    pub fn command(&mut self, vm: &mut Chip8, line: &str) -> (Action, String) {
+        let line = line.trim();
+        let line = if line.is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.to_string();
+            line.to_string()
+        };
+
+        match self.run_command(vm, &line) {
+            Ok((action, output)) => {
+                self.resuming = action == Action::Resume;
+                (action, output)
+            }
+            Err(message) => (Action::Stay, message),
+        }
+    }
+
+    fn run_command(&mut self, vm: &mut Chip8, line: &str) -> Result<(Action, String), String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let arg = |index: usize| args.get(index).map(|word| parse_number(word)).transpose();
+
+        let output = match command {
+            "" => String::new(),
+            "help" | "h" | "?" => String::from(HELP),
+            "break" | "b" => {
+                let address = arg(0)?.ok_or("break needs an address")?;
+                let condition = match args.get(1) {
+                    Some(&"if") => Some(Condition::parse(&args[2..].join(" "))?),
+                    Some(other) => return Err(format!("Expected 'if', not '{}'", other)),
+                    None => None,
+                };
+                let breakpoint = Breakpoint { address, condition };
+                self.breakpoints.push(breakpoint);
+                format!("Breakpoint at {}", breakpoint)
+            }
+            "delete" | "d" => match arg(0)? {
+                Some(address) => {
+                    let before = self.breakpoints.len();
+                    self.breakpoints.retain(|b| b.address != address);
+                    format!("Deleted {} breakpoint(s)", before - self.breakpoints.len())
+                }
+                None => {
+                    self.breakpoints.clear();
+                    String::from("Deleted all breakpoints")
+                }
+            },
+            "watch" | "rwatch" | "awatch" => {
+                let start = arg(0)?.ok_or_else(|| format!("{} needs an address", command))?;
+                let length = arg(1)?.unwrap_or(1).max(1);
+                let watchpoint = Watchpoint {
+                    start: start as usize,
+                    end: start as usize + length as usize - 1,
+                    on_read: command != "watch",
+                    on_write: command != "rwatch",
+                };
+                self.watchpoints.push(watchpoint);
+                format!("Watchpoint at {}", watchpoint)
+            }
+            "unwatch" => match arg(0)? {
+                Some(address) => {
+                    let before = self.watchpoints.len();
+                    self.watchpoints
+                        .retain(|w| !(w.start..=w.end).contains(&(address as usize)));
+                    format!("Deleted {} watchpoint(s)", before - self.watchpoints.len())
+                }
+                None => {
+                    self.watchpoints.clear();
+                    String::from("Deleted all watchpoints")
+                }
+            },
+            "breakpoints" | "bl" => {
+                let lines: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|b| format!("break {}", b))
+                    .chain(self.watchpoints.iter().map(|w| format!("watch {}", w)))
+                    .collect();
+                if lines.is_empty() {
+                    String::from("No breakpoints or watchpoints")
+                } else {
+                    lines.join("\n")
+                }
+            }
+            "continue" | "c" => {
+                self.target = None;
+                return Ok((Action::Resume, String::new()));
+            }
+            "step" | "s" => {
+                let count = arg(0)?.unwrap_or(1);
+                let mut lines = vec![];
+                for _ in 0..count {
+                    lines.push(current_instruction(vm));
+                    match self.step(vm) {
+                        Ok(None) => {}
+                        Ok(Some(reason)) => {
+                            lines.push(reason);
+                            break;
+                        }
+                        Err(error) => {
+                            lines.push(format!("VM fault: {}", error));
+                            break;
+                        }
+                    }
+                }
+                lines.push(vm.get_pretty_debug_info());
+                lines.join("\n")
+            }
+            "next" | "n" => {
+                let opcode = read_word(vm, vm.pc());
+                if opcode & 0xF000 != 0x2000 {
+                    return self.run_command(vm, "step");
+                }
+                self.target = Some(Target::StackDepth(vm.stack().len()));
+                return Ok((Action::Resume, String::new()));
+            }
+            "finish" | "out" => {
+                let depth = vm.stack().len();
+                if depth == 0 {
+                    return Err(String::from("Not in a subroutine"));
+                }
+                self.target = Some(Target::StackDepth(depth - 1));
+                return Ok((Action::Resume, String::new()));
+            }
+            "until" | "u" => {
+                let address = arg(0)?.ok_or("until needs an address")?;
+                self.target = Some(Target::Address(address));
+                return Ok((Action::Resume, String::new()));
+            }
+            "regs" | "r" => format!("{}\nStack: {:03X?}", vm.get_pretty_debug_info(), vm.stack()),
+            "set" => {
+                let name = args.first().ok_or("set needs a register")?;
+                let value = arg(1)?.ok_or("set needs a value")?;
+                match name.to_uppercase().as_str() {
+                    "PC" => vm.set_pc(value),
+                    _ => match parse_location(name)? {
+                        Location::I => vm.set_i(value),
+                        Location::Register(_) if value > 0xFF => {
+                            return Err(format!("{:X} doesn't fit in a register", value))
+                        }
+                        Location::Register(register) => vm.set_register(register, value as u8),
+                    },
+                }
+                vm.get_pretty_debug_info()
+            }
+            "dump" | "x" => {
+                let address = arg(0)?.unwrap_or_else(|| vm.i());
+                let length = arg(1)?.unwrap_or(0x40);
+                hexdump(vm.memory(), address as usize, length as usize)
+            }
+            "poke" | "p" => {
+                let address = arg(0)?.ok_or("poke needs an address")?;
+                if args.len() < 2 {
+                    return Err(String::from("poke needs some bytes to write"));
+                }
+                for (offset, _) in args[1..].iter().enumerate() {
+                    let value = arg(offset + 1)?.unwrap_or(0);
+                    if value > 0xFF {
+                        return Err(format!("{:X} isn't a byte", value));
+                    }
+                    vm.poke(address as usize + offset, value as u8)
+                        .map_err(|error| error.to_string())?;
+                }
+                hexdump(vm.memory(), address as usize, args.len() - 1)
+            }
+            "dis" => {
+                let mut address = arg(0)?.unwrap_or_else(|| vm.pc());
+                let count = arg(1)?.unwrap_or(10);
+                let mut lines = vec![];
+                for _ in 0..count {
+                    let text = disassemble_at(vm.memory(), address);
+                    lines.push(format!("{:03X}: {}", address, text));
+                    address =
+                        address.wrapping_add(if text.starts_with("LD I, LONG") { 4 } else { 2 });
+                }
+                lines.join("\n")
+            }
+            "quit" | "q" => return Ok((Action::Quit, String::new())),
+            _ => return Err(format!("Unknown command '{}', try 'help'", command)),
+        };
+
+        Ok((Action::Stay, output))
+    }
+
+    /// Run a frame like [`Chip8::run_frame`], but check for breakpoints before
+    /// each instruction and watchpoints after it. Returns why we stopped if we
+    /// did, in which case the timers haven't ticked, and the frontend should go
+    /// back to taking commands.
+    pub fn run_frame(
+        &mut self,
+        vm: &mut Chip8,
+        instructions_per_frame: usize,
+    ) -> Result<Option<String>, Chip8Error> {
+        for _ in 0..instructions_per_frame {
+            if vm.is_halted() {
+                break;
+            }
+            if !std::mem::take(&mut self.resuming) {
+                if let Some(reason) = self.should_stop(vm) {
+                    return Ok(Some(format!("{}\n{}", reason, current_instruction(vm))));
+                }
+            }
+            if let Some(reason) = self.step(vm)? {
+                self.target = None;
+                return Ok(Some(format!("{}\n{}", reason, current_instruction(vm))));
+            }
+        }
+
+        vm.tick_timers();
+
+        Ok(None)
+    }
+
+    fn should_stop(&mut self, vm: &Chip8) -> Option<String> {
+        let reached = match self.target {
+            Some(Target::Address(address)) => vm.pc() == address,
+            Some(Target::StackDepth(depth)) => vm.stack().len() <= depth,
+            None => false,
+        };
+        if reached {
+            self.target = None;
+            return Some(format!("Stopped at {:03X}", vm.pc()));
+        }
+
+        // like gdb, hitting a breakpoint abandons any next/finish/until
+        let breakpoint = self
+            .breakpoints
+            .iter()
+            .find(|b| b.address == vm.pc() && b.condition.iter().all(|c| c.holds(vm)))?;
+        self.target = None;
+        Some(format!("Breakpoint at {}", breakpoint))
+    }
+
+    /// Execute one instruction, through the tracer if there is one. Returns why
+    /// the program should stop if it touched a watchpoint.
+    pub fn step(&mut self, vm: &mut Chip8) -> Result<Option<String>, Chip8Error> {
+        let pc = vm.pc();
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer.execute(vm)?,
+            None => vm.execute_next_instruction()?,
+        }
+        Ok(self.watchpoint_hit(vm, pc))
+    }
+
+    // Whether the instruction at pc, which just ran, touched any watched memory.
+    fn watchpoint_hit(&self, vm: &Chip8, pc: u16) -> Option<String> {
+        vm.memory_accesses().iter().find_map(|access| {
+            let watchpoint = self.watchpoints.iter().find(|w| w.matches(access))?;
+            let what = match access.kind {
+                AccessKind::Read => format!("read {:02X} from {:03X}", access.value, access.addr),
+                AccessKind::Write => format!("wrote {:02X} to {:03X}", access.value, access.addr),
+            };
+            Some(format!(
+                "Watchpoint at {}: {:03X}: {} ({:04X}) {}",
+                watchpoint,
+                pc,
+                disassemble_at(vm.memory(), pc),
+                read_word(vm, pc),
+                what
+            ))
+        })
+    }
+}
+
+// The instruction at PC, with its address.
+fn current_instruction(vm: &Chip8) -> String {
+    format!("{:03X}: {}", vm.pc(), disassemble_at(vm.memory(), vm.pc()))
+}
+
+fn read_word(vm: &Chip8, address: u16) -> u16 {
+    let byte = |a: u16| *vm.memory().get(a as usize).unwrap_or(&0) as u16;
+    byte(address) << 8 | byte(address.wrapping_add(1))
+}
+
+// 16 bytes to a line, with the address at the start of each.
+fn hexdump(memory: &[u8], address: usize, length: usize) -> String {
+    let end = (address + length).min(memory.len());
+    let mut lines = vec![];
+    for start in (address..end).step_by(16) {
+        let bytes: Vec<String> = memory[start..end.min(start + 16)]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        lines.push(format!("{:03X}: {}", start, bytes.join(" ")));
+    }
+    if lines.is_empty() {
+        format!("{:03X} is past the end of memory", address)
+    } else {
+        lines.join("\n")
+    }
+}
+
+// Hex, with or without a # or 0x in front.
+fn parse_number(word: &str) -> Result<u16, String> {
+    let digits = word.trim();
+    let digits = digits
+        .strip_prefix('#')
+        .or_else(|| digits.strip_prefix("0x"))
+        .unwrap_or(digits);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("'{}' isn't a hex number", word.trim()))
+}
+
+fn parse_location(word: &str) -> Result<Location, String> {
+    let word = word.trim().to_uppercase();
+    if word == "I" {
+        return Ok(Location::I);
+    }
+    match word
+        .strip_prefix('V')
+        .map(|digit| usize::from_str_radix(digit, 16))
+    {
+        Some(Ok(register)) if register < 16 => Ok(Location::Register(register)),
+        _ => Err(format!("'{}' isn't a register (V0-VF or I)", word)),
+    }
+}
@@ -0,0 +1,313 @@
+// Turns opcodes back into assembly, using Cowgod's mnemonics with CHIPPER's
+// number syntax (# for hex), plus the SUPER-CHIP and XO-CHIP additions. The
+// listings it produces for whole programs can be fed back into the assembler.
+
+use std::collections::{BTreeSet, HashSet};
+
+/// Disassemble a single opcode, e.g. 0xD125 becomes `DRW V1, V2, 5`. Anything
+/// that isn't an instruction comes out as a data word, `DW #xxxx`.
+pub fn disassemble(opcode: u16) -> String {
+    mnemonic(opcode, &|address| format!("#{:03X}", address))
+        .unwrap_or_else(|| format!("DW #{:04X}", opcode))
+}
+
+/// Disassemble the instruction at the given address, including the operand of
+/// an XO-CHIP long load. Addresses past the end of memory read as zero.
+pub fn disassemble_at(memory: &[u8], address: u16) -> String {
+    let opcode = read_word(memory, address as usize);
+    if opcode == 0xF000 {
+        format!(
+            "LD I, LONG #{:04X}",
+            read_word(memory, address as usize + 2)
+        )
+    } else {
+        disassemble(opcode)
+    }
+}
+
+fn read_word(memory: &[u8], address: usize) -> u16 {
+    let byte = |a: usize| *memory.get(a).unwrap_or(&0) as u16;
+    byte(address) << 8 | byte(address + 1)
+}
+
+// The mnemonic for an opcode, or None if it isn't one. target formats the
+// address operand of jumps and calls, so listings can substitute labels.
+fn mnemonic(opcode: u16, target: &dyn Fn(u16) -> String) -> Option<String> {
+    let x = (opcode >> 8) & 0xf;
+    let y = (opcode >> 4) & 0xf;
+    let n = opcode & 0xf;
+    let kk = opcode & 0xff;
+    let nnn = opcode & 0xfff;
+
+    let text = match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => String::from("CLS"),
+            0x00EE => String::from("RET"),
+            0x00FB => String::from("SCR"),
+            0x00FC => String::from("SCL"),
+            0x00FD => String::from("EXIT"),
+            0x00FE => String::from("LOW"),
+            0x00FF => String::from("HIGH"),
+            _ if opcode & 0xFFF0 == 0x00C0 => format!("SCD {}", n),
+            _ if opcode & 0xFFF0 == 0x00D0 => format!("SCU {}", n),
+            _ => format!("SYS #{:03X}", nnn),
+        },
+        0x1 => format!("JP {}", target(nnn)),
+        0x2 => format!("CALL {}", target(nnn)),
+        0x3 => format!("SE V{:X}, #{:02X}", x, kk),
+        0x4 => format!("SNE V{:X}, #{:02X}", x, kk),
+        0x5 => match n {
+            0x0 => format!("SE V{:X}, V{:X}", x, y),
+            0x2 => format!("SAVE V{:X} - V{:X}", x, y),
+            0x3 => format!("LOAD V{:X} - V{:X}", x, y),
+            _ => return None,
+        },
+        0x6 => format!("LD V{:X}, #{:02X}", x, kk),
+        0x7 => format!("ADD V{:X}, #{:02X}", x, kk),
+        0x8 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            // Vy is optional for the shifts, and most programs leave it as 0
+            0x6 if y == 0 => format!("SHR V{:X}", x),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0xE if y == 0 => format!("SHL V{:X}", x),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => return None,
+        },
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {}", target(nnn)),
+        0xB => format!("JP V0, {}", target(nnn)),
+        0xC => format!("RND V{:X}, #{:02X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => return None,
+        },
+        0xF => match kk {
+            0x00 if x == 0 => String::from("LD I, LONG"),
+            0x01 => format!("PLANE {}", x),
+            0x02 if x == 0 => String::from("AUDIO"),
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x3A => format!("PITCH V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+/// One line of a program listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    /// The raw bytes this line covers.
+    pub bytes: Vec<u8>,
+    /// Set if something jumps to or calls this address.
+    pub label: Option<String>,
+    /// The instruction, or a `DB` line for anything that looks like data.
+    pub text: String,
+    pub is_code: bool,
+}
+
+/// Disassemble a whole program loaded at `origin` (normally 0x200).
+///
+/// To tell code from data, we follow every path execution can take from the
+/// start of the program: through jumps, calls and both sides of skips, stopping
+/// at returns and anything that doesn't decode. Whatever we never reach is data
+/// (usually sprites), and is listed as bytes. `JP V0` can go anywhere, so code
+/// that's only reachable through a jump table will show up as data.
+pub fn disassemble_program(rom: &[u8], origin: u16) -> Vec<Line> {
+    let (code, targets) = trace_code(rom, origin);
+    listing(rom, origin, &code, &targets)
+}
+
+/// Disassemble a program as if every two bytes were an instruction.
+pub fn disassemble_linear(rom: &[u8], origin: u16) -> Vec<Line> {
+    let end = origin as usize + rom.len();
+    let code = (origin as usize..end).step_by(2).collect();
+    listing(rom, origin, &code, &BTreeSet::new())
+}
+
+fn label_name(address: u16) -> String {
+    format!("L{:03X}", address)
+}
+
+// Follow control flow from the origin. Returns the addresses instructions start
+// at, and the addresses that are jumped to or called.
+fn trace_code(rom: &[u8], origin: u16) -> (BTreeSet<usize>, BTreeSet<u16>) {
+    let start = origin as usize;
+    let end = start + rom.len();
+    let word = |address: usize| read_word(rom, address - start);
+
+    let mut code = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![start];
+
+    while let Some(address) = pending.pop() {
+        if address < start || address + 1 >= end || !visited.insert(address) {
+            continue;
+        }
+
+        let opcode = word(address);
+        if mnemonic(opcode, &label_name).is_none() {
+            continue;
+        }
+        code.insert(address);
+
+        let size = if opcode == 0xF000 { 4 } else { 2 };
+        let next = address + size;
+        // the instruction after next, for skips; a skipped long load is 4 bytes
+        let after_next = next + if word(next) == 0xF000 { 4 } else { 2 };
+        let nnn = opcode & 0xfff;
+        // only addresses inside the program can have a label
+        let mut jump_to = |target: u16| {
+            if (start..end).contains(&(target as usize)) {
+                targets.insert(target);
+            }
+            pending.push(target as usize);
+        };
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00EE || opcode == 0x00FD => {}
+            0x1 => jump_to(nnn),
+            0x2 => {
+                jump_to(nnn);
+                pending.push(next);
+            }
+            // SAVE and LOAD are the only 5xyn instructions that don't skip
+            0x5 if opcode & 0xf != 0 => pending.push(next),
+            0x3 | 0x4 | 0x5 | 0x9 | 0xE => {
+                pending.push(next);
+                pending.push(after_next);
+            }
+            0xB => {}
+            _ => pending.push(next),
+        }
+    }
+
+    (code, targets)
+}
+
+fn listing(rom: &[u8], origin: u16, code: &BTreeSet<usize>, targets: &BTreeSet<u16>) -> Vec<Line> {
+    let start = origin as usize;
+    let end = start + rom.len();
+
+    // Lay the lines out first, as (address, size, is code). A jump can land in
+    // the middle of another instruction, where a label would never be defined,
+    // so only the targets that start a line get one.
+    let mut spans = vec![];
+    let mut address = start;
+    while address < end {
+        if code.contains(&address) && address + 1 < end {
+            let size = if read_word(rom, address - start) == 0xF000 {
+                4
+            } else {
+                2
+            };
+            let size = size.min(end - address);
+            spans.push((address, size, true));
+            address += size;
+        } else {
+            // gather data up to the next instruction or label, 8 bytes to a line
+            let mut data_end = address + 1;
+            while data_end < end
+                && data_end - address < 8
+                && !code.contains(&data_end)
+                && !targets.contains(&(data_end as u16))
+            {
+                data_end += 1;
+            }
+            spans.push((address, data_end - address, false));
+            address = data_end;
+        }
+    }
+
+    let labels: BTreeSet<u16> = spans
+        .iter()
+        .map(|(address, _, _)| *address as u16)
+        .filter(|address| targets.contains(address))
+        .collect();
+    let target = |address: u16| {
+        if labels.contains(&address) {
+            label_name(address)
+        } else {
+            format!("#{:03X}", address)
+        }
+    };
+
+    let mut lines = vec![];
+    for (address, size, is_code) in spans {
+        let bytes = rom[address - start..address - start + size].to_vec();
+        let text = if is_code {
+            let opcode = read_word(rom, address - start);
+            if opcode == 0xF000 {
+                let long = read_word(rom, address - start + 2);
+                format!("LD I, LONG #{:04X}", long)
+            } else {
+                mnemonic(opcode, &target).unwrap_or_else(|| format!("DW #{:04X}", opcode))
+            }
+        } else {
+            format!(
+                "DB {}",
+                bytes
+                    .iter()
+                    .map(|b| format!("#{:02X}", b))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        };
+        let label = if labels.contains(&(address as u16)) {
+            Some(label_name(address as u16))
+        } else {
+            None
+        };
+        lines.push(Line {
+            address: address as u16,
+            bytes,
+            label,
+            text,
+            is_code,
+        });
+    }
+
+    lines
+}
+
+/// Format a listing as assembly source, with the address and raw bytes of each
+/// line in a comment. Assembling it gives back the same bytes.
+pub fn format_listing(lines: &[Line]) -> String {
+    // code can follow an odd number of bytes of data
+    let mut output = String::from("    ALIGN OFF\n");
+    for line in lines {
+        if let Some(label) = &line.label {
+            output.push_str(&format!("{}:\n", label));
+        }
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        output.push_str(&format!(
+            "    {:<32}; {:03X}: {}\n",
+            line.text,
+            line.address,
+            bytes.join(" ")
+        ));
+    }
+    output
+}
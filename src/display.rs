@@ -0,0 +1,189 @@
+use std::ops::Index;
+
+// The original CHIP-8 resolution, and the SUPER-CHIP high resolution mode.
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// The framebuffer. Each pixel is a byte, zero for off and non-zero for on, and
+/// rows can be indexed directly: `display[y][x]`. For XO-CHIP programs the two
+/// low bits are the two bit planes, so a pixel is one of four colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new(LORES_WIDTH, LORES_HEIGHT)
+    }
+}
+
+impl Display {
+    pub fn new(width: usize, height: usize) -> Display {
+        Display {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    // Rebuild a display from its pixels, as long as the size is one we support.
+    pub(crate) fn from_pixels(width: usize, height: usize, pixels: &[u8]) -> Option<Display> {
+        let supported = (width, height) == (LORES_WIDTH, LORES_HEIGHT)
+            || (width, height) == (HIRES_WIDTH, HIRES_HEIGHT);
+        if !supported || pixels.len() != width * height {
+            return None;
+        }
+        Some(Display {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub(crate) fn pixel_mut(&mut self, x: usize, y: usize) -> &mut u8 {
+        &mut self.pixels[y * self.width + x]
+    }
+
+    // Switching resolution throws away whatever was on screen, same as Octo.
+    pub(crate) fn resize(&mut self, width: usize, height: usize) {
+        *self = Display::new(width, height);
+    }
+
+    /// One line of text per row, `#` for a lit pixel and `.` for an unlit one.
+    /// On XO-CHIP, `#` is lit on the first plane only, `2` on the second only
+    /// and `3` on both.
+    pub fn to_ascii(&self) -> String {
+        let mut output = String::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            for pixel in row {
+                output.push(match pixel {
+                    0 => '.',
+                    1 => '#',
+                    2 => '2',
+                    _ => '3',
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// The display drawn with Unicode half blocks, two pixel rows per line of text.
+    pub fn to_half_blocks(&self) -> String {
+        let mut output = String::new();
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let top = self.get(x, y) != 0;
+                let bottom = y + 1 < self.height && self.get(x, y + 1) != 0;
+                output.push(match (top, bottom) {
+                    (false, false) => ' ',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (true, true) => '\u{2588}',
+                });
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// The display drawn with Unicode braille, a 2x4 block of pixels per character.
+    pub fn to_braille(&self) -> String {
+        // which bit of the braille character each dot in the 2x4 block is
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        let mut output = String::new();
+        for y in (0..self.height).step_by(4) {
+            for x in (0..self.width).step_by(2) {
+                let mut bits = 0;
+                for (dy, row) in DOTS.iter().enumerate() {
+                    for (dx, bit) in row.iter().enumerate() {
+                        if y + dy < self.height
+                            && x + dx < self.width
+                            && self.get(x + dx, y + dy) != 0
+                        {
+                            bits |= bit;
+                        }
+                    }
+                }
+                output.push(std::char::from_u32(0x2800 + bits).unwrap_or(' '));
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// The display as a plain (P1) PBM image, with any lit pixel in black.
+    pub fn to_pbm(&self) -> String {
+        let mut output = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.pixels.chunks(self.width) {
+            let line: Vec<&str> = row
+                .iter()
+                .map(|pixel| if *pixel == 0 { "0" } else { "1" })
+                .collect();
+            output.push_str(&line.join(" "));
+            output.push('\n');
+        }
+        output
+    }
+
+    // Turn off every pixel in the given planes.
+    pub(crate) fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    // Move the given planes dx pixels right and dy pixels down (negative for left
+    // and up). Whatever scrolls in from the edges is blank.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let old = self.pixels.clone();
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if from_x >= 0 && from_x < width && from_y >= 0 && from_y < height {
+                    old[(from_y * width + from_x) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | moved;
+            }
+        }
+    }
+}
+
+impl Index<usize> for Display {
+    type Output = [u8];
+
+    fn index(&self, y: usize) -> &[u8] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while loading or running a program.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The opcode at `pc` doesn't decode to any instruction we know about.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// 00EE was executed with nothing on the stack.
+    StackUnderflow,
+    /// 2nnn was executed with the stack already full.
+    StackOverflow,
+    /// An instruction tried to read or write outside of memory.
+    MemoryOutOfBounds { addr: usize },
+    /// The program doesn't fit in memory after the interpreter area.
+    RomTooLarge,
+    /// A save state file is corrupt, or from an incompatible version.
+    InvalidSnapshot(&'static str),
+    /// A save state was taken while a different program was loaded.
+    SnapshotRomMismatch,
+    /// The program couldn't be read from disk.
+    Io(io::Error),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unrecognized opcode {:04X} at {:03X}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow => {
+                write!(f, "Returned from a subroutine with an empty stack")
+            }
+            Chip8Error::StackOverflow => write!(f, "Called a subroutine with a full stack"),
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "Memory access out of bounds at {:X}", addr)
+            }
+            Chip8Error::RomTooLarge => write!(f, "Program is too large to fit in memory"),
+            Chip8Error::InvalidSnapshot(reason) => write!(f, "Invalid save state: {}", reason),
+            Chip8Error::SnapshotRomMismatch => {
+                write!(f, "Save state is for a different program")
+            }
+            Chip8Error::Io(error) => write!(f, "Problem opening file: {}", error),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Chip8Error {
+        Chip8Error::Io(error)
+    }
+}
@@ -0,0 +1,521 @@
+// A stub for GDB's remote serial protocol, so GDB (or anything else that speaks
+// it) can debug a program over TCP.
+//
+// Registers, in GDB's numbering, are V0-VF (0-15), I (16), PC (17), SP (18, the
+// stack depth), DT (19) and ST (20). I and PC are 16 bits, sent little-endian
+// like every other GDB target; the rest are a byte each. GDB doesn't know about
+// CHIP-8, so the layout is described to it in target.xml. Memory is the whole
+// address space.
+//
+// The stub doesn't own the machine or a thread: a frontend calls frame() once a
+// frame instead of running the frame itself, and the stub answers whatever the
+// debugger has sent and runs the program if it's been told to continue.
+
+use crate::chip8::{AccessKind, MemoryAccess};
+use crate::{Chip8, Chip8Error};
+
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+// bytes per register, in GDB's numbering
+const REGISTER_SIZES: [usize; 21] = [
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1,
+];
+
+/// What the program is doing after [`GdbStub::frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Nothing has connected yet, so the program hasn't started.
+    Waiting,
+    /// Stopped at a breakpoint, after a step, or when the debugger attached.
+    Stopped,
+    Running,
+    /// The debugger killed the program, so the frontend should quit.
+    Killed,
+}
+
+// A Z2 (write), Z3 (read) or Z4 (either) watchpoint on len bytes from addr.
+#[derive(PartialEq)]
+struct Watchpoint {
+    kind: u8,
+    addr: usize,
+    len: usize,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind = match self.kind {
+            2 => access.kind == AccessKind::Write,
+            3 => access.kind == AccessKind::Read,
+            _ => true,
+        };
+        kind && self.addr <= access.addr && access.addr - self.addr < self.len
+    }
+
+    // what the stop reply calls it
+    fn name(&self) -> &'static str {
+        match self.kind {
+            2 => "watch",
+            3 => "rwatch",
+            _ => "awatch",
+        }
+    }
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    connection: Option<TcpStream>,
+    // what's been received but not handled yet
+    input: Vec<u8>,
+    status: Status,
+    // the reply to `?`, why the program last stopped
+    last_stop: String,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    // set when continuing, so we don't stop at the breakpoint we're sitting on
+    resuming: bool,
+}
+
+impl GdbStub {
+    /// Listen for a debugger on `addr`, like `127.0.0.1:1234`.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbStub {
+            listener,
+            connection: None,
+            input: vec![],
+            status: Status::Waiting,
+            last_stop: String::from("S05"),
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+            resuming: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Handle everything the debugger has sent since the last frame, then run
+    /// a frame of `instructions_per_frame` instructions if the program is
+    /// running, stopping at breakpoints and watchpoints along the way. Call
+    /// this once a frame in place of [`Chip8::run_frame`].
+    pub fn frame(&mut self, vm: &mut Chip8, instructions_per_frame: usize) -> io::Result<Status> {
+        if self.connection.is_none() && self.status != Status::Killed {
+            self.accept()?;
+        }
+
+        self.receive(vm);
+
+        if self.status == Status::Running {
+            self.run(vm, instructions_per_frame);
+        }
+
+        Ok(self.status)
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        match self.listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(true)?;
+                stream.set_nodelay(true)?;
+                self.connection = Some(stream);
+                self.input.clear();
+                self.status = Status::Stopped;
+                self.last_stop = String::from("S05");
+                Ok(())
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    // When the debugger goes away, the program carries on without it.
+    fn disconnect(&mut self) {
+        self.connection = None;
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        if self.status != Status::Killed {
+            self.status = Status::Running;
+        }
+    }
+
+    fn receive(&mut self, vm: &mut Chip8) {
+        let mut buffer = [0; 1024];
+        while let Some(stream) = self.connection.as_mut() {
+            match stream.read(&mut buffer) {
+                Ok(0) => self.disconnect(),
+                Ok(read) => self.input.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.disconnect(),
+            }
+        }
+
+        while let Some(packet) = self.next_packet() {
+            if let Some(reply) = self.handle(vm, &packet) {
+                self.send(&reply);
+            }
+        }
+    }
+
+    // Takes the next whole packet out of the input, acknowledging it. Acks
+    // from the debugger are skipped over, and a Ctrl-C (a bare 0x03) stops
+    // the program.
+    fn next_packet(&mut self) -> Option<String> {
+        loop {
+            match self.input.first() {
+                None => return None,
+                Some(b'$') => {
+                    let hash = self.input.iter().position(|&b| b == b'#')?;
+                    if self.input.len() < hash + 3 {
+                        return None;
+                    }
+                    let data: Vec<u8> = self.input[1..hash].to_vec();
+                    let checksum =
+                        String::from_utf8_lossy(&self.input[hash + 1..hash + 3]).into_owned();
+                    self.input.drain(..hash + 3);
+
+                    if u8::from_str_radix(&checksum, 16).ok() == Some(checksum_of(&data)) {
+                        self.write(b"+");
+                        return Some(String::from_utf8_lossy(&data).into_owned());
+                    }
+                    self.write(b"-");
+                }
+                Some(0x03) => {
+                    self.input.remove(0);
+                    if self.status == Status::Running {
+                        self.stop(String::from("S02"));
+                    }
+                }
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.write(packet.as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let stream = match self.connection.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+        // replies are small, so it's simplest to block until they're sent
+        let sent = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.write_all(bytes))
+            .and_then(|_| stream.set_nonblocking(true));
+        if sent.is_err() {
+            self.disconnect();
+        }
+    }
+
+    fn stop(&mut self, reply: String) {
+        self.status = Status::Stopped;
+        self.send(&reply);
+        self.last_stop = reply;
+    }
+
+    // The reply to a packet, or None if it doesn't get one (yet).
+    fn handle(&mut self, vm: &mut Chip8, packet: &str) -> Option<String> {
+        let mut chars = packet.chars();
+        let command = chars.next()?;
+        let args = chars.as_str();
+
+        let reply = match command {
+            '?' => self.last_stop.clone(),
+            'g' => to_hex(&register_bytes(vm)),
+            'G' => match from_hex(args) {
+                Some(bytes) if bytes.len() == register_bytes(vm).len() => {
+                    let mut bytes = &bytes[..];
+                    let mut written = true;
+                    for (n, size) in REGISTER_SIZES.iter().enumerate() {
+                        written &= set_register(vm, n, &bytes[..*size]);
+                        bytes = &bytes[*size..];
+                    }
+                    String::from(if written { "OK" } else { "E01" })
+                }
+                _ => String::from("E01"),
+            },
+            'p' => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_SIZES.len() => {
+                    let offset: usize = REGISTER_SIZES[..n].iter().sum();
+                    to_hex(&register_bytes(vm)[offset..offset + REGISTER_SIZES[n]])
+                }
+                _ => String::from("E01"),
+            },
+            'P' => {
+                let (n, value) = split(args, '=');
+                match (usize::from_str_radix(n, 16), from_hex(value)) {
+                    (Ok(n), Some(value))
+                        if n < REGISTER_SIZES.len() && value.len() == REGISTER_SIZES[n] =>
+                    {
+                        let written = set_register(vm, n, &value);
+                        String::from(if written { "OK" } else { "E01" })
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            'm' => {
+                let (addr, len) = split(args, ',');
+                match (parse_hex(addr), parse_hex(len)) {
+                    (Some(addr), Some(len)) => match end_of(vm, addr, len) {
+                        Some(end) => to_hex(&vm.memory()[addr..end]),
+                        None => String::from("E01"),
+                    },
+                    _ => String::from("E01"),
+                }
+            }
+            'M' => {
+                let (range, data) = split(args, ':');
+                let (addr, len) = split(range, ',');
+                match (parse_hex(addr), parse_hex(len), from_hex(data)) {
+                    (Some(addr), Some(len), Some(data))
+                        if data.len() == len && end_of(vm, addr, len).is_some() =>
+                    {
+                        for (offset, byte) in data.iter().enumerate() {
+                            // can't fail, the whole range is in memory
+                            let _ = vm.poke(addr + offset, *byte);
+                        }
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            'Z' | 'z' => self.set_point(command == 'Z', args),
+            's' => {
+                if let Some(addr) = parse_hex(args) {
+                    vm.set_pc(addr as u16);
+                }
+                let reply = self.execute(vm).unwrap_or_else(|| String::from("S05"));
+                self.last_stop = reply.clone();
+                reply
+            }
+            'c' => {
+                if let Some(addr) = parse_hex(args) {
+                    vm.set_pc(addr as u16);
+                }
+                self.status = Status::Running;
+                self.resuming = true;
+                return None;
+            }
+            'k' => {
+                self.status = Status::Killed;
+                self.connection = None;
+                return None;
+            }
+            'D' => {
+                self.send("OK");
+                self.disconnect();
+                return None;
+            }
+            'H' | 'T' => String::from("OK"),
+            'q' => query(args),
+            // anything else isn't supported, which is an empty reply
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    // Z/z packets, `type,addr,kind`: 0 and 1 are breakpoints, 2-4 watchpoints.
+    fn set_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next().and_then(|kind| kind.parse::<u8>().ok());
+        let addr = fields.next().and_then(parse_hex);
+        let len = fields.next().and_then(parse_hex);
+        match (kind, addr, len) {
+            (Some(0), Some(addr), Some(_)) | (Some(1), Some(addr), Some(_)) => {
+                if insert {
+                    self.breakpoints.insert(addr as u16);
+                } else {
+                    self.breakpoints.remove(&(addr as u16));
+                }
+                String::from("OK")
+            }
+            (Some(kind @ 2..=4), Some(addr), Some(len)) => {
+                if addr.checked_add(len).is_none() {
+                    return String::from("E01");
+                }
+                let watchpoint = Watchpoint { kind, addr, len };
+                if insert {
+                    self.watchpoints.push(watchpoint);
+                } else {
+                    self.watchpoints.retain(|w| *w != watchpoint);
+                }
+                String::from("OK")
+            }
+            (Some(_), Some(_), Some(_)) => String::new(),
+            _ => String::from("E01"),
+        }
+    }
+
+    fn run(&mut self, vm: &mut Chip8, instructions_per_frame: usize) {
+        for _ in 0..instructions_per_frame {
+            if !self.resuming && self.breakpoints.contains(&vm.pc()) {
+                self.stop(String::from("S05"));
+                return;
+            }
+            self.resuming = false;
+
+            if let Some(reply) = self.execute(vm) {
+                self.stop(reply);
+                return;
+            }
+        }
+
+        vm.tick_timers();
+    }
+
+    // Execute an instruction, returning the stop reply if the program should
+    // stop because of it.
+    fn execute(&mut self, vm: &mut Chip8) -> Option<String> {
+        if !vm.is_halted() {
+            if let Err(error) = vm.execute_next_instruction() {
+                let signal = match error {
+                    Chip8Error::UnknownOpcode { .. } => "S04",
+                    _ => "S0b",
+                };
+                return Some(String::from(signal));
+            }
+        }
+        if vm.is_halted() {
+            return Some(String::from("W00"));
+        }
+
+        for access in vm.memory_accesses() {
+            if let Some(watchpoint) = self.watchpoints.iter().find(|w| w.matches(access)) {
+                return Some(format!("T05{}:{:x};", watchpoint.name(), access.addr));
+            }
+        }
+        None
+    }
+}
+
+// The replies to q packets.
+fn query(args: &str) -> String {
+    let (name, rest) = split(args, ':');
+    match name {
+        "Supported" => String::from("PacketSize=1000;qXfer:features:read+"),
+        "Attached" => String::from("1"),
+        "fThreadInfo" => String::from("m1"),
+        "sThreadInfo" => String::from("l"),
+        "Xfer" => {
+            // features:read:target.xml:offset,length
+            let (range, annex) = match rest.rsplitn(2, ':').collect::<Vec<_>>()[..] {
+                [range, annex] => (range, annex),
+                _ => return String::from("E01"),
+            };
+            if annex != "features:read:target.xml" {
+                return String::new();
+            }
+            let (offset, length) = split(range, ',');
+            let xml = target_xml();
+            match (parse_hex(offset), parse_hex(length)) {
+                (Some(offset), Some(length)) => {
+                    let start = offset.min(xml.len());
+                    let end = (start + length).min(xml.len());
+                    let more = if end < xml.len() { 'm' } else { 'l' };
+                    format!("{}{}", more, &xml[start..end])
+                }
+                _ => String::from("E01"),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn target_xml() -> String {
+    let mut registers = String::new();
+    for n in 0..16 {
+        registers += &format!(
+            "    <reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>\n",
+            n
+        );
+    }
+    format!(
+        "<?xml version=\"1.0\"?>
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">
+<target version=\"1.0\">
+  <feature name=\"org.chip8.core\">
+{}    <reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>
+    <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>
+    <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>
+    <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>
+    <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>
+  </feature>
+</target>
+",
+        registers
+    )
+}
+
+// Every register, in GDB's order.
+fn register_bytes(vm: &Chip8) -> Vec<u8> {
+    let mut bytes = vm.registers().to_vec();
+    bytes.extend_from_slice(&vm.i().to_le_bytes());
+    bytes.extend_from_slice(&vm.pc().to_le_bytes());
+    bytes.push(vm.stack().len() as u8);
+    bytes.push(vm.dt());
+    bytes.push(vm.st());
+    bytes
+}
+
+// Returns false if the value doesn't fit, which can only happen to SP: the
+// stack is 16 deep.
+fn set_register(vm: &mut Chip8, n: usize, bytes: &[u8]) -> bool {
+    match n {
+        0..=15 => vm.set_register(n, bytes[0]),
+        16 => vm.set_i(u16::from_le_bytes([bytes[0], bytes[1]])),
+        17 => vm.set_pc(u16::from_le_bytes([bytes[0], bytes[1]])),
+        18 => return vm.set_stack_depth(bytes[0] as usize).is_ok(),
+        19 => vm.set_dt(bytes[0]),
+        20 => vm.set_st(bytes[0]),
+        _ => return false,
+    }
+    true
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn split(text: &str, separator: char) -> (&str, &str) {
+    let mut parts = text.splitn(2, separator);
+    (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+}
+
+// The end of len bytes of memory from addr, if they're all in memory. The
+// numbers come off the network, so they can be anything.
+fn end_of(vm: &Chip8, addr: usize, len: usize) -> Option<usize> {
+    addr.checked_add(len)
+        .filter(|end| *end <= vm.memory().len())
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 == 1 || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,22 @@
+// SHA-1 hashes, which pick out ROMs everywhere (profiles, keymaps, save states
+// and movies), and their 40-digit hex form for the text formats.
+
+pub(crate) fn sha1(bytes: &[u8]) -> [u8; 20] {
+    sha1_smol::Sha1::from(bytes).digest().bytes()
+}
+
+pub(crate) fn to_hex(hash: &[u8; 20]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Either case is fine, since the chip-8-database has both.
+pub(crate) fn from_hex(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (n, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[n * 2..n * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
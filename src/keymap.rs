@@ -0,0 +1,212 @@
+// How host keyboard keys map onto the CHIP-8's hex keypad. The usual layout puts
+// the keypad on the left side of a QWERTY keyboard:
+//
+//     1 2 3 C        1 2 3 4
+//     4 5 6 D   <-   Q W E R
+//     7 8 9 E        A S D F
+//     A 0 B F        Z X C V
+//
+// A keymap file can change that for every program, or for just one.
+
+use crate::hash::from_hex;
+use crate::profile::Profile;
+
+use std::fs;
+
+/// The default layout, as (key name, CHIP-8 key) pairs. Key names are the ones
+/// SDL uses, which for letters and digits is just the upper case character.
+pub const DEFAULT_LAYOUT: [(&str, u8); 16] = [
+    ("1", 0x1),
+    ("2", 0x2),
+    ("3", 0x3),
+    ("4", 0xC),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("E", 0x6),
+    ("R", 0xD),
+    ("A", 0x7),
+    ("S", 0x8),
+    ("D", 0x9),
+    ("F", 0xE),
+    ("Z", 0xA),
+    ("X", 0x0),
+    ("C", 0xB),
+    ("V", 0xF),
+];
+
+/// Which host keys press which CHIP-8 keys. A CHIP-8 key can have any number of
+/// host keys, and a host key can press more than one CHIP-8 key at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    // (host key name, CHIP-8 key) pairs
+    bindings: Vec<(String, u8)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap {
+            bindings: DEFAULT_LAYOUT
+                .iter()
+                .map(|(name, key)| (name.to_string(), *key))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The CHIP-8 keys a host key presses, given its SDL name in any case.
+    pub fn keys_for(&self, host_key: &str) -> Vec<u8> {
+        self.bindings
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(host_key))
+            .map(|(_, key)| *key)
+            .collect()
+    }
+
+    /// The host keys that press a CHIP-8 key.
+    pub fn host_keys(&self, chip8_key: u8) -> Vec<&str> {
+        self.bindings
+            .iter()
+            .filter(|(_, key)| *key == chip8_key)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    // Rebind the keys a table of the config file mentions. The CHIP-8 keys in it
+    // lose their old host keys, and the host keys in it stop pressing whatever
+    // they used to, so moving a key somewhere else doesn't leave it doing both.
+    fn apply(&mut self, table: &[(u8, Vec<String>)]) {
+        self.bindings.retain(|(name, key)| {
+            table.iter().all(|(chip8_key, host_keys)| {
+                *chip8_key != *key && !host_keys.iter().any(|n| n.eq_ignore_ascii_case(name))
+            })
+        });
+        for (chip8_key, host_keys) in table {
+            for name in host_keys {
+                self.bindings.push((name.clone(), *chip8_key));
+            }
+        }
+    }
+}
+
+// The host key for each thing the profile database says a program's keys do.
+const ACTION_KEYS: [(&str, &str); 6] = [
+    ("up", "Up"),
+    ("down", "Down"),
+    ("left", "Left"),
+    ("right", "Right"),
+    ("a", "Space"),
+    ("b", "Left Shift"),
+];
+
+// A CHIP-8 key and the host keys that should press it, for each key in a table.
+type Table = Vec<(u8, Vec<String>)>;
+
+/// A keymap file, in TOML. The `[keys]` table changes the default layout for
+/// every program, and a `[roms.<sha1>]` table changes it again for just the
+/// program with that SHA-1:
+///
+/// ```toml
+/// [keys]
+/// 5 = ["W", "Up"]
+/// 8 = ["S", "Down"]
+///
+/// [roms.0ab3c4d8b5e6b8f1b9f2c1b84d1a5a2e7c3e1f04]
+/// name = "PONG"
+/// 1 = "W"
+/// 4 = "S"
+/// ```
+///
+/// Each CHIP-8 key (a hex digit) gets a host key name, or a list of them. Names
+/// are SDL's, in any case. A `name` is just there for people reading the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeymapConfig {
+    keys: Table,
+    roms: Vec<([u8; 20], Table)>,
+}
+
+impl KeymapConfig {
+    pub fn parse(text: &str) -> Result<KeymapConfig, String> {
+        let document: toml::Value = text.parse().map_err(|error| format!("{}", error))?;
+        let document = document
+            .as_table()
+            .ok_or_else(|| String::from("expected a table"))?;
+
+        let mut config = KeymapConfig::default();
+        for (name, value) in document {
+            match name.as_str() {
+                "keys" => config.keys = parse_table("keys", value)?,
+                "roms" => {
+                    let roms = value
+                        .as_table()
+                        .ok_or_else(|| String::from("roms: expected a table for each program"))?;
+                    for (hash, table) in roms {
+                        let section = format!("roms.{}", hash);
+                        let rom_hash = from_hex(hash)
+                            .ok_or_else(|| format!("{}: expected a SHA-1 hash", section))?;
+                        config.roms.push((rom_hash, parse_table(&section, table)?));
+                    }
+                }
+                other => return Err(format!("unknown section '{}'", other)),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> Result<KeymapConfig, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        KeymapConfig::parse(&text)
+    }
+
+    /// The keymap for the program with this SHA-1 (see [`Chip8::rom_hash`]).
+    /// If its profile says which keys do what, the arrow keys, space and shift
+    /// press those too, unless the file says otherwise.
+    ///
+    /// [`Chip8::rom_hash`]: crate::Chip8::rom_hash
+    pub fn keymap_for(&self, rom_hash: [u8; 20], profile: Option<&Profile>) -> Keymap {
+        let mut keymap = Keymap::default();
+        for (action, chip8_key) in profile.map_or(&[][..], |p| &p.keys[..]) {
+            if let Some((_, host_key)) = ACTION_KEYS.iter().find(|(a, _)| a == action) {
+                keymap.bindings.retain(|(name, _)| name != host_key);
+                keymap.bindings.push((host_key.to_string(), *chip8_key));
+            }
+        }
+        keymap.apply(&self.keys);
+        for (hash, table) in &self.roms {
+            if *hash == rom_hash {
+                keymap.apply(table);
+            }
+        }
+        keymap
+    }
+}
+
+// One table of CHIP-8 keys and their host keys, `section` naming it for errors.
+fn parse_table(section: &str, value: &toml::Value) -> Result<Table, String> {
+    let error = |message: &str| format!("{}: {}", section, message);
+    let entries = value
+        .as_table()
+        .ok_or_else(|| error("expected a table of keys"))?;
+
+    let mut table = vec![];
+    for (key, value) in entries {
+        if key == "name" {
+            continue;
+        }
+        let chip8_key = match u8::from_str_radix(key, 16) {
+            Ok(k) if k < 16 && key.len() == 1 => k,
+            _ => return Err(error(&format!("'{}' isn't a CHIP-8 key (0-F)", key))),
+        };
+        let names = match value {
+            toml::Value::String(name) => vec![name.clone()],
+            toml::Value::Array(names) => names
+                .iter()
+                .map(|name| name.as_str().map(String::from))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| error(&format!("{}: expected key names", key)))?,
+            _ => return Err(error(&format!("{}: expected a key name or a list", key))),
+        };
+        table.push((chip8_key, names));
+    }
+    Ok(table)
+}
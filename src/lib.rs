@@ -0,0 +1,44 @@
+//! A CHIP-8 virtual machine, following [Cowgod's spec](http://devernay.free.fr/hacks/chip8/C8TECH10.HTM).
+//!
+//! The core has no dependency on any particular frontend. Create a [`Chip8`] with the
+//! [`Quirks`] your program expects, load a program into it with
+//! [`Chip8::load_application`] or [`Chip8::load_rom`], then call [`Chip8::run_frame`]
+//! 60 times a second, drawing [`Chip8::display`] and feeding input through
+//! [`Chip8::register_keydown`] in between. [`Chip8::execute_next_instruction`] steps a
+//! single instruction, for debuggers and the like.
+//!
+//! Sound is left to the frontend too: once a frame, tell an [`audio::AudioSink`]
+//! whether [`Chip8::st`] is non-zero, and pass on [`Chip8::audio_pattern`] once
+//! [`Chip8::audio_pattern_loaded`] says an XO-CHIP program has set one.
+//!
+//! Nothing in the VM panics on a misbehaving program; faults come back as a
+//! [`Chip8Error`] so the frontend can decide what to do about them.
+
+pub mod asm;
+pub mod audio;
+pub mod chip8;
+// only public so the binaries can share it; see the top of cli.rs
+#[doc(hidden)]
+pub mod cli;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod error;
+pub mod gdb;
+mod hash;
+pub mod keymap;
+pub mod movie;
+pub mod palette;
+pub mod phosphor;
+pub mod profile;
+pub mod quirks;
+pub mod rewind;
+pub mod script;
+pub mod snapshot;
+pub mod trace;
+
+pub use crate::chip8::Chip8;
+pub use crate::display::Display;
+pub use crate::error::Chip8Error;
+pub use crate::quirks::Quirks;
+pub use crate::snapshot::Snapshot;
@@ -1,91 +1,476 @@
-extern crate sdl2;
-#[macro_use]
-extern crate maplit;
-
-mod chip8;
-
-use chip8::Chip8;
+use chip8_interpreter::audio::{AudioSink, Oscillator, Tone, Waveform};
+use chip8_interpreter::cli::{Args, HeadlessOptions, MachineOptions, SessionOptions};
+use chip8_interpreter::debugger::{Action, Debugger};
+use chip8_interpreter::disasm::disassemble_at;
+use chip8_interpreter::keymap::KeymapConfig;
+use chip8_interpreter::movie::Movie;
+use chip8_interpreter::palette::Palette;
+use chip8_interpreter::phosphor::Phosphor;
+use chip8_interpreter::rewind::Rewind;
+use chip8_interpreter::{Chip8, Chip8Error, Display, Snapshot};
 
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::{FullscreenType, Window};
 
-use std::env;
-use std::thread::sleep;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, sleep};
 use std::time::Duration;
 use std::time::Instant;
 
-fn resize_window(window: &mut sdl2::video::Window, x: u32, y: u32) {
-    window.set_size(x, y).unwrap();
+const WINDOW_TITLE: &str = "Critter's Amazing Chip8 Emulator";
+
+// Size the window for the picture, or when it fills the screen, scale the
+// picture to fit it instead.
+fn resize_window(canvas: &mut Canvas<Window>, width: u32, height: u32) {
+    if canvas.window().fullscreen_state() == FullscreenType::Off {
+        canvas.window_mut().set_size(width, height).unwrap();
+    } else {
+        canvas.set_logical_size(width, height).unwrap();
+    }
 }
 
-fn main() {
-    // map real keypresses to what the VM expects
-    let key_remapping = hashmap! {
-        "1" => 0x1,
-        "2" => 0x2,
-        "3" => 0x3,
-        "4" => 0xC,
-        "Q" => 0x4,
-        "W" => 0x5,
-        "E" => 0x6,
-        "R" => 0xD,
-        "A" => 0x7,
-        "S" => 0x8,
-        "D" => 0x9,
-        "F" => 0xE,
-        "Z" => 0xA,
-        "X" => 0x0,
-        "C" => 0xB,
-        "V" => 0xF,
+// How many window pixels across a CHIP-8 pixel is. The scale is for lores, and
+// hires pixels are half that, rounded up so each gets at least one and the
+// window (which is sized to fit) doesn't end up with a strip left undrawn.
+fn pixel_size(scale: u32, display: &Display) -> u32 {
+    scale.div_ceil(display.width() as u32 / 64)
+}
+
+fn open_window(scale: u32, fullscreen: bool) -> Result<Canvas<Window>, String> {
+    let video_subsystem = sdl2::init()?.video()?;
+    let mut window = video_subsystem.window(WINDOW_TITLE, 64 * scale, 32 * scale);
+    window.position_centered();
+    if fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().map_err(|error| error.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    if fullscreen {
+        resize_window(&mut canvas, 64 * scale, 32 * scale);
+    }
+    Ok(canvas)
+}
+
+// Feeds the oscillator to SDL whenever the device isn't paused.
+struct Beep(Oscillator);
+
+impl AudioCallback for Beep {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out);
+    }
+}
+
+// Plays the beep through an SDL audio device, which we just pause and resume.
+struct SdlBeeper {
+    device: AudioDevice<Beep>,
+    playing: bool,
+    // the XO-CHIP pattern the oscillator is playing, if any
+    pattern: Option<([u8; 16], f64)>,
+}
+
+impl SdlBeeper {
+    fn new(audio: &sdl2::AudioSubsystem, tone: Tone) -> Result<SdlBeeper, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio.open_playback(None, &spec, |spec| {
+            Beep(Oscillator::new(tone, spec.freq as u32))
+        })?;
+
+        Ok(SdlBeeper {
+            device,
+            playing: false,
+            pattern: None,
+        })
+    }
+}
+
+impl AudioSink for SdlBeeper {
+    fn pattern(&mut self, pattern: &[u8; 16], rate: f64) {
+        // only lock the audio thread out when something's changed
+        if self.pattern != Some((*pattern, rate)) {
+            self.device.lock().0.set_pattern(*pattern, rate);
+            self.pattern = Some((*pattern, rate));
+        }
+    }
+
+    fn frame(&mut self, playing: bool) {
+        if playing != self.playing {
+            if playing {
+                self.device.resume();
+            } else {
+                self.device.pause();
+            }
+            self.playing = playing;
+        }
+    }
+}
+
+const USAGE: &str = "usage: chip8_interpreter [options] <rom>
+
+options:
+  --scale <n>        how many window pixels across a CHIP-8 pixel is (default 8)
+  --ips <n>          instructions per second, instead of --ipf; also --hz
+  --palette <name>   dark (white on black, the default), light, green, amber,
+                     lcd or octo, or your own colors, e.g. #000000,#33ff66
+                     (unlit and lit, then XO-CHIP's second plane and both)
+  --grid             leave a gap between pixels
+  --phosphor <0-1>   let pixels fade out over a few frames, like a CRT, to
+                     hide flicker; how much brightness is left each frame
+                     (default 0, off)
+  --keymap <file>    which keys press what, from a keymap file
+  --fullscreen       fill the screen
+  --paused           start paused; Pause resumes and period steps
+  --debug            start paused, with a debugger prompt in the terminal
+  --headless         run without a window or sound as fast as possible,
+                     then print the screen, like chip8-headless
+  --frames <n>       how many frames --headless runs (default 600, or the
+                     length of the movie with --play)
+  --beep-freq <hz>   pitch of the beep (default 440)
+  --volume <0-1>     volume of the beep (default 0.25)
+  --waveform <shape> square, triangle, sawtooth or sine
+  --rewind-seconds <n>
+                     how far back Backspace rewinds (default 10, 0 turns it off)";
+
+// SDL's version of a palette color.
+fn rgb(color: [u8; 3]) -> Color {
+    Color::RGB(color[0], color[1], color[2])
+}
+
+struct Options {
+    program: String,
+    scale: u32,
+    machine: MachineOptions,
+    session: SessionOptions,
+    palette: Option<Palette>,
+    grid: bool,
+    phosphor: f32,
+    keymap: KeymapConfig,
+    fullscreen: bool,
+    paused: bool,
+    debug: bool,
+    headless: bool,
+    // only --frames is taken for --headless; the rest stay at their defaults
+    headless_options: HeadlessOptions,
+    tone: Tone,
+    rewind_seconds: u32,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        program: String::new(),
+        scale: 8,
+        machine: MachineOptions::default(),
+        session: SessionOptions::default(),
+        palette: None,
+        grid: false,
+        phosphor: 0.0,
+        keymap: KeymapConfig::default(),
+        fullscreen: false,
+        paused: false,
+        debug: false,
+        headless: false,
+        headless_options: HeadlessOptions::default(),
+        tone: Tone::default(),
+        rewind_seconds: 10,
     };
+    let mut program = None;
+
+    let mut args = Args::new(format!(
+        "{}\n{}\n{}",
+        USAGE,
+        MachineOptions::USAGE,
+        SessionOptions::USAGE
+    ));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                options.scale = args.parse(&arg);
+                if options.scale == 0 {
+                    args.error("--scale must be at least 1");
+                }
+            }
+            // we run a frame at a time at 60 hz, which is how often the timers tick
+            "--ips" | "--hz" => {
+                let per_second: usize = args.parse(&arg);
+                options.machine.instructions_per_frame = Some((per_second / 60).max(1));
+            }
+            "--palette" => {
+                let name = args.value(&arg);
+                options.palette = Some(Palette::parse(&name).unwrap_or_else(|error| {
+                    let names: Vec<&str> = Palette::PRESETS.iter().map(|(n, _)| *n).collect();
+                    args.error(&format!(
+                        "Invalid palette '{}': {}. Choose one of: {}, or give colors",
+                        name,
+                        error,
+                        names.join(", ")
+                    ))
+                }));
+            }
+            "--grid" => options.grid = true,
+            "--phosphor" => options.phosphor = args.parse(&arg),
+            "--keymap" => {
+                let path = args.value(&arg);
+                options.keymap = KeymapConfig::load(&path).unwrap_or_else(|error| {
+                    args.error(&format!("Couldn't load {}: {}", path, error))
+                });
+            }
+            "--fullscreen" => options.fullscreen = true,
+            "--paused" => options.paused = true,
+            "--debug" => options.debug = true,
+            "--headless" => options.headless = true,
+            "--frames" => options.headless_options.frames = Some(args.parse(&arg)),
+            "--beep-freq" => options.tone.frequency = args.parse(&arg),
+            "--volume" => options.tone.volume = args.parse::<f32>(&arg).clamp(0.0, 1.0),
+            "--waveform" => {
+                let name = args.value(&arg);
+                options.tone.waveform = Waveform::from_name(&name).unwrap_or_else(|| {
+                    args.error(&format!(
+                        "Unknown waveform '{}'. Choose one of: square, triangle, sawtooth, sine",
+                        name
+                    ))
+                });
+            }
+            "--rewind-seconds" => options.rewind_seconds = args.parse(&arg),
+            "-h" | "--help" => args.help(),
+            _ if options.machine.parse(&arg, &mut args) => {}
+            _ if options.session.parse(&arg, &mut args) => {}
+            _ if arg.starts_with("--") => args.error(&format!("Unknown option {}", arg)),
+            _ => program = Some(arg),
+        }
+    }
+
+    match program {
+        Some(program) => options.program = program,
+        None => args.error("No program specified!"),
+    }
+    if options.headless && options.debug {
+        args.error("--debug needs a window, so it can't be used with --headless");
+    }
+    if options.headless_options.frames.is_some() && !options.headless {
+        args.error("--frames only makes sense with --headless");
+    }
+    // the debugger can change the machine in ways a movie can't record
+    options.session.check(&options.machine, &args);
+    options.headless_options.check(&options.session, &args);
+    if options.debug && (options.session.play.is_some() || options.session.record.is_some()) {
+        args.error("--debug can't be used with --record or --play");
+    }
+
+    options
+}
+
+// Write out the movie we recorded, if we were recording one.
+fn save_recording(recording: Option<Movie>, path: Option<String>) {
+    if let (Some(movie), Some(path)) = (recording, path) {
+        match movie.save(&path) {
+            Ok(()) => println!("Recorded {} frames to {}", movie.len(), path),
+            Err(error) => eprintln!("Couldn't write {}: {}", path, error),
+        }
+    }
+}
+
+// The save state slot for each of the function keys that have one.
+fn save_slot(keycode: Keycode) -> Option<u32> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        _ => None,
+    }
+}
+
+// Save states live next to the program, e.g. games/PONG.state1
+fn save_slot_path(program: &str, slot: u32) -> String {
+    format!("{}.state{}", program, slot)
+}
+
+// Reads debugger commands from standard input on another thread, so the window
+// keeps responding while we wait for them.
+fn read_commands() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    io::stdout().flush().unwrap_or_default();
+}
+
+// Tell the user what went wrong and leave the VM state up for inspection.
+fn report_fault(canvas: &mut Canvas<Window>, vm: &Chip8, error: &Chip8Error) {
+    eprintln!("VM fault: {}", error);
+    eprintln!("{}", vm.get_pretty_debug_info());
+    canvas
+        .window_mut()
+        .set_title(&format!("FAULT: {} (paused)", error))
+        .unwrap();
+}
 
-    let mut vm = Chip8::new();
+fn main() {
+    let Options {
+        program,
+        scale,
+        machine,
+        session,
+        palette,
+        mut grid,
+        phosphor,
+        keymap,
+        fullscreen,
+        paused,
+        debug,
+        headless,
+        headless_options,
+        tone,
+        rewind_seconds,
+    } = parse_options();
 
-    // Assume the first argument is a path to to an application
-    match env::args().nth(1) {
-        Some(path) => vm.load_application(&path),
-        None => panic!("No program specified!"),
+    // the same run as chip8-headless, and nothing from SDL gets opened
+    if headless {
+        headless_options.run(&program, &machine, &session);
     }
 
+    let (mut vm, instructions_per_frame) = machine
+        .load(&program, session.play.as_ref())
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+    if let Some(profile) = vm.profile() {
+        println!(
+            "{} ({})",
+            profile.title,
+            profile.platform().unwrap_or("unknown platform")
+        );
+    }
+    let mut palette = palette
+        .or_else(|| vm.profile().and_then(Palette::from_profile))
+        .unwrap_or(Palette::PRESETS[0].1);
+
+    // breakpoints are checked whether or not there's a prompt to set them from
+    let mut debugger = Debugger::new();
+    match session.tracer() {
+        Ok(Some(tracer)) => debugger.set_tracer(tracer),
+        Ok(None) => {}
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+
+    // Movies are a frame-by-frame record of the session, so going back in time
+    // with rewind or a save state isn't allowed while one's recording or playing.
+    let SessionOptions {
+        record, mut play, ..
+    } = session;
+    let mut recording = record
+        .as_ref()
+        .map(|_| Movie::new(&vm, instructions_per_frame));
+
+    // map real keypresses to what the VM expects, which can depend on the program
+    let keymap = keymap.keymap_for(vm.rom_hash(), vm.profile());
+
+    // what's drawn is the display after it's been through the phosphor filter,
+    // which does nothing unless it's been asked for
+    let mut phosphor = Phosphor::new(phosphor);
+
     // scale pixels by
     let scaler = 4;
-    let mut current_scale = 8;
+    let mut current_scale = scale;
+    // what the window is sized for, which changes with the scale and resolution
+    let mut window_size = (64 * scale, 32 * scale);
 
     // set up the SDL window
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window(
-            "Critter's Amazing Chip8 Emulator",
-            64 * current_scale,
-            32 * current_scale,
-        )
-        .position_centered()
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
+    let mut canvas = match open_window(current_scale, fullscreen) {
+        Ok(canvas) => canvas,
+        Err(error) => {
+            eprintln!("Couldn't open a window: {}", error);
+            process::exit(1);
+        }
+    };
+    let sdl_context = canvas.window().subsystem().sdl();
+
+    // no audio device isn't worth stopping over, we just don't beep
+    let mut beeper = match sdl_context
+        .audio()
+        .and_then(|audio| SdlBeeper::new(&audio, tone))
+    {
+        Ok(beeper) => Some(beeper),
+        Err(error) => {
+            eprintln!(
+                "Couldn't open an audio device, sound is disabled: {}",
+                error
+            );
+            None
+        }
+    };
 
     // this is used to get keyboard input
-    let mut event_pump: sdl2::EventPump = sdl_context.event_pump().unwrap();
+    let mut event_pump = match sdl_context.event_pump() {
+        Ok(event_pump) => event_pump,
+        Err(error) => {
+            eprintln!("Couldn't read the keyboard: {}", error);
+            process::exit(1);
+        }
+    };
 
-    // variables needed for calculating the delay to emulate a slower clock speed
-    let mut delay_counter = 0u32;
-    let sixty_hz = (10_u32).pow(9) / 60;
-    let cycle_time = (10_u32).pow(9) / 500;
-    let mut t1: Instant;
-    let mut t2: u32;
-    let mut draw_screen = true;
+    // We run a frame at a time at 60 hz, which is how often the timers tick, and
+    // execute enough instructions per frame to get the clock speed asked for.
+    let frame_time = Duration::from_nanos(10_u64.pow(9) / 60);
+    let mut frame_start: Instant;
 
     // variables for pausing and single-stepping instructions
-    let mut paused = false;
+    let mut paused = paused || debug;
     let mut step_instruction = false;
+    let commands = if debug {
+        println!(
+            "Paused at {:03X}, type 'help' for debugger commands",
+            vm.pc()
+        );
+        prompt();
+        Some(read_commands())
+    } else {
+        None
+    };
+
+    // Set when the VM hits a fault. Nothing runs until a rewind or a loaded
+    // state takes it back, even if it's unpaused, so the bad instruction isn't
+    // run again.
+    let mut faulted = false;
+
+    // the last few seconds of frames, for rewinding
+    let mut rewind = Rewind::new(rewind_seconds);
+
+    let mut movie_frame = 0;
 
     // Main event loop
     'running: loop {
         // get starting time
-        t1 = Instant::now();
+        frame_start = Instant::now();
 
         // get input
         for event in event_pump.poll_iter() {
@@ -119,7 +504,6 @@ fn main() {
                     } else {
                         current_scale += scaler;
                     }
-                    resize_window(canvas.window_mut(), 64 * current_scale, 32 * current_scale);
                 }
                 // Pause pauses the emulator, allowing for single-stepping instructions
                 Event::KeyDown {
@@ -132,7 +516,11 @@ fn main() {
                         continue 'running;
                     } else {
                         paused = true;
-                        step_instruction = true;
+                        // a movie only records whole frames
+                        step_instruction = recording.is_none() && play.is_none();
+                        if commands.is_some() {
+                            prompt();
+                        }
                     };
                 }
                 // When paused, Period advances the emulator a single instruction
@@ -141,81 +529,237 @@ fn main() {
                     repeat: false,
                     ..
                 } => {
+                    if recording.is_some() || play.is_some() {
+                        eprintln!("Can't step while a movie is recording or playing");
+                        continue;
+                    }
                     step_instruction = true;
                 }
+                // F5 cycles through the built-in palettes
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => {
+                    let next = Palette::PRESETS
+                        .iter()
+                        .position(|(_, preset)| *preset == palette)
+                        .map_or(0, |n| (n + 1) % Palette::PRESETS.len());
+                    let (name, preset) = Palette::PRESETS[next];
+                    palette = preset;
+                    println!("Palette: {}", name);
+                }
+                // F6 turns the pixel grid on and off
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => grid = !grid,
+                // F1-F4 save the state to a slot, and Shift+F1-F4 load it back
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if save_slot(keycode).is_some() => {
+                    let slot = save_slot(keycode).unwrap_or_default();
+                    let path = save_slot_path(&program, slot);
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        if recording.is_some() || play.is_some() {
+                            eprintln!("Can't load states while a movie is recording or playing");
+                            continue;
+                        }
+                        match Snapshot::load(&path).and_then(|state| vm.load_state(&state)) {
+                            Ok(()) => {
+                                println!("Loaded state from slot {}", slot);
+                                faulted = false;
+                            }
+                            Err(error) => eprintln!("Couldn't load slot {}: {}", slot, error),
+                        }
+                    } else {
+                        match vm.save_state().save(&path) {
+                            Ok(()) => println!("Saved state to slot {}", slot),
+                            Err(error) => eprintln!("Couldn't save slot {}: {}", slot, error),
+                        }
+                    }
+                }
                 _ => {}
             }
         }
 
-        // send keypresses to chip8
-        vm.register_keydown(
-            event_pump
-                .keyboard_state()
-                .pressed_scancodes()
-                .filter_map(|k| {
-                    Some(*key_remapping.get::<str>(&Keycode::from_scancode(k)?.name())?)
-                }),
-        );
+        // typing a command while the program is running pauses it first
+        while let Some(line) = commands.as_ref().and_then(|c| c.try_recv().ok()) {
+            let (action, output) = debugger.command(&mut vm, &line);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            match action {
+                Action::Stay => {
+                    paused = true;
+                    prompt();
+                }
+                Action::Resume => paused = false,
+                Action::Quit => break 'running,
+            }
+        }
+
+        // send keypresses to chip8, or the movie's once it's playing
+        match play.as_ref().and_then(|movie| movie.keys_at(movie_frame)) {
+            Some(keys) => vm.register_keydown(keys.into_iter()),
+            None => vm.register_keydown(
+                event_pump
+                    .keyboard_state()
+                    .pressed_scancodes()
+                    .filter_map(Keycode::from_scancode)
+                    .flat_map(|k| keymap.keys_for(&k.name())),
+            ),
+        }
 
         // draw display
-        if draw_screen {
-            // clear canvas to white
-            canvas.set_draw_color(Color::RGB(255, 255, 255));
+        {
+            let display = vm.display();
+            let pixel_size = pixel_size(current_scale, display);
+            let size = (
+                display.width() as u32 * pixel_size,
+                display.height() as u32 * pixel_size,
+            );
+            if size != window_size {
+                resize_window(&mut canvas, size.0, size.1);
+                window_size = size;
+            }
+
+            // With the grid on, every pixel is drawn a little smaller than its
+            // square and the grid color shows through around it. Otherwise we
+            // clear to the unlit color and only draw the lit pixels.
+            let gap = if grid && pixel_size >= 3 { 1 } else { 0 };
+            let background = if gap > 0 {
+                palette.grid()
+            } else {
+                palette.color(0)
+            };
+            canvas.set_draw_color(rgb(background));
             canvas.clear();
-            // set color to black
-            canvas.set_draw_color(Color::RGB(0, 0, 0));
-
-            for y in 0..32 as i32 {
-                for x in 0..64 as i32 {
-                    if vm.display()[y as usize][x as usize] == 0 {
-                        canvas
-                            .fill_rect(Rect::new(
-                                x * current_scale as i32,
-                                y * current_scale as i32,
-                                current_scale,
-                                current_scale,
-                            ))
-                            .unwrap();
+
+            // Gather the pixels up by color and draw each color in one go.
+            // XO-CHIP pixels can be any of four, and fading ones anything between.
+            phosphor.update(display);
+            let mut batches: BTreeMap<[u8; 3], Vec<Rect>> = BTreeMap::new();
+            for y in 0..display.height() {
+                for x in 0..display.width() {
+                    let color = palette.faded(phosphor.get(x, y));
+                    if color != background {
+                        batches.entry(color).or_default().push(Rect::new(
+                            x as i32 * pixel_size as i32,
+                            y as i32 * pixel_size as i32,
+                            pixel_size - gap,
+                            pixel_size - gap,
+                        ));
                     }
                 }
             }
-
-            draw_screen = false;
+            for (color, rects) in batches {
+                canvas.set_draw_color(rgb(color));
+                canvas.fill_rects(&rects).unwrap();
+            }
 
             // update display
             canvas.present();
         }
 
-        if paused {
-            if step_instruction {
-                vm.execute_next_instruction();
-                println!("{}", vm.get_pretty_debug_info());
-                step_instruction = false;
+        // holding Backspace plays the machine backwards a frame at a time
+        let rewinding = recording.is_none()
+            && play.is_none()
+            && event_pump
+                .keyboard_state()
+                .is_scancode_pressed(Scancode::Backspace);
+
+        if rewinding {
+            match rewind.step_back(&mut vm) {
+                Ok(true) if faulted => {
+                    // back to before whatever went wrong
+                    faulted = false;
+                    canvas.window_mut().set_title(WINDOW_TITLE).unwrap();
+                }
+                Ok(_) => {}
+                Err(error) => eprintln!("Couldn't rewind: {}", error),
+            }
+        } else if paused {
+            if step_instruction && !faulted {
+                println!("{:03X}: {}", vm.pc(), disassemble_at(vm.memory(), vm.pc()));
+                match debugger.step(&mut vm) {
+                    Ok(hit) => {
+                        if let Some(reason) = hit {
+                            println!("{}", reason);
+                        }
+                        println!("{}", vm.get_pretty_debug_info());
+                    }
+                    Err(error) => {
+                        report_fault(&mut canvas, &vm, &error);
+                        faulted = true;
+                    }
+                }
             }
-        } else {
-            vm.execute_next_instruction();
+            step_instruction = false;
+        } else if !faulted {
+            rewind.record(&vm);
 
-            // the timer needs to decrement at 60 hz, realtime
-            t2 = t1.elapsed().as_nanos() as u32;
-            delay_counter += if cycle_time > t2 { cycle_time - t2 } else { 0 };
-            while delay_counter > sixty_hz {
-                delay_counter -= sixty_hz;
-                if vm.dt > 0 {
-                    vm.dt -= 1;
-                };
-                if vm.st > 0 {
-                    vm.st -= 1;
-                };
+            match debugger.run_frame(&mut vm, instructions_per_frame) {
+                Ok(None) => {
+                    if let Some(movie) = recording.as_mut() {
+                        movie.record(&vm);
+                    }
+                    if let Some(movie) = play.as_ref() {
+                        // either way, the keyboard takes over from here
+                        if let Err(error) = movie.verify(movie_frame, &vm) {
+                            eprintln!("Movie {}", error);
+                            play = None;
+                        } else if movie_frame + 1 == movie.len() as u64 {
+                            println!("Movie finished");
+                            play = None;
+                        }
+                    }
+                    movie_frame += 1;
+                }
+                Ok(Some(reason)) => {
+                    println!("{}", reason);
+                    if commands.is_some() {
+                        prompt();
+                    }
+                    paused = true;
+                    continue 'running;
+                }
+                Err(error) => {
+                    // drop into the paused debugger rather than crashing
+                    report_fault(&mut canvas, &vm, &error);
+                    if commands.is_some() {
+                        prompt();
+                    }
+                    faulted = true;
+                    paused = true;
+                    continue 'running;
+                }
+            }
 
-                // we're drawing the screen as 60hz too, because why not
-                draw_screen = true;
+            // SUPER-CHIP programs can exit with 00FD
+            if vm.is_halted() {
+                break 'running;
             }
+        }
 
-            // one iteration of this loop is going to go a lot faster than 500hz, so
-            // we sleep for the missing time
-            if cycle_time > t2 {
-                sleep(Duration::new(0, cycle_time - t2));
+        // beep while the sound timer is running, but not while we're paused
+        if let Some(beeper) = beeper.as_mut() {
+            if vm.audio_pattern_loaded() {
+                beeper.pattern(vm.audio_pattern(), vm.audio_playback_rate());
             }
+            beeper.frame(!paused && !faulted && !rewinding && vm.st() > 0);
+        }
+
+        // a frame's worth of instructions takes a lot less than a 60th of a
+        // second, so we sleep for the missing time
+        if let Some(remaining) = frame_time.checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
         }
     }
+
+    save_recording(recording, record);
 }
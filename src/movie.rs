@@ -0,0 +1,271 @@
+// Input movies: a recording of the keys held on every frame of a session,
+// along with everything else needed to play it back exactly (the program, the
+// random seed, the quirks and the speed). Every so often the recording also
+// notes a hash of the whole machine state, so playback can tell if it's drifted
+// from what was recorded.
+//
+// Movies are text, one line per frame after a short header:
+//
+//     chip8 movie 1
+//     rom 2b1e1b5f2c3e...
+//     seed 1234
+//     ipf 8
+//     quirks load_store_increments_i
+//     frames
+//     0000
+//     0020
+//     0020 9c4f7d...
+//
+// A frame is the keys held, as a hex bitmask (bit n is key n), plus the state
+// hash after it on every 60th frame.
+
+use crate::hash::{self, from_hex, to_hex};
+use crate::{Chip8, Quirks};
+
+use std::fs;
+
+const HEADER: &str = "chip8 movie 1";
+
+// How many frames go by between state hashes.
+const HASH_INTERVAL: usize = 60;
+
+// The name each quirk goes by in the quirks line.
+const QUIRK_NAMES: [&str; 6] = [
+    "shift_uses_vy",
+    "load_store_increments_i",
+    "jump_uses_vx",
+    "sprite_wrap",
+    "logic_resets_vf",
+    "display_wait",
+];
+
+fn quirk_flags(quirks: &mut Quirks) -> [&mut bool; 6] {
+    [
+        &mut quirks.shift_uses_vy,
+        &mut quirks.load_store_increments_i,
+        &mut quirks.jump_uses_vx,
+        &mut quirks.sprite_wrap,
+        &mut quirks.logic_resets_vf,
+        &mut quirks.display_wait,
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    // bit n is set if key n was held
+    keys: u16,
+    // SHA-1 of the machine's save state after the frame
+    hash: Option<[u8; 20]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: [u8; 20],
+    seed: u64,
+    quirks: Quirks,
+    instructions_per_frame: usize,
+    frames: Vec<Frame>,
+}
+
+impl Movie {
+    /// Start recording a session on a machine that's just loaded its program.
+    /// Recording has to start there, since the seed is all that's kept of the
+    /// random number generator.
+    pub fn new(vm: &Chip8, instructions_per_frame: usize) -> Movie {
+        Movie {
+            rom_hash: vm.rom_hash(),
+            seed: vm.seed(),
+            quirks: vm.quirks(),
+            instructions_per_frame,
+            frames: vec![],
+        }
+    }
+
+    /// A machine set up to play the movie back, with the same seed and quirks
+    /// as the one it was recorded on. Load the same program into it.
+    pub fn machine(&self) -> Chip8 {
+        Chip8::with_seed(self.quirks, self.seed)
+    }
+
+    pub fn rom_hash(&self) -> [u8; 20] {
+        self.rom_hash
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
+    /// How many frames were recorded.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Record a frame that's just been run, with the keys that were held
+    /// during it.
+    pub fn record(&mut self, vm: &Chip8) {
+        let keys = vm
+            .keys_pressed()
+            .iter()
+            .enumerate()
+            .fold(0u16, |keys, (k, pressed)| keys | (*pressed as u16) << k);
+        let hash = if self.frames.len() % HASH_INTERVAL == HASH_INTERVAL - 1 {
+            Some(state_hash(vm))
+        } else {
+            None
+        };
+        self.frames.push(Frame { keys, hash });
+    }
+
+    /// The keys to hold down during `frame`, or None once the movie is over.
+    pub fn keys_at(&self, frame: u64) -> Option<Vec<u8>> {
+        let keys = self.frames.get(frame as usize)?.keys;
+        Some((0..16).filter(|k| keys & (1 << k) != 0).collect())
+    }
+
+    /// Check that the program is the one the movie was recorded with.
+    pub fn check_rom(&self, vm: &Chip8) -> Result<(), String> {
+        if vm.rom_hash() == self.rom_hash {
+            Ok(())
+        } else {
+            Err(String::from(
+                "the movie was recorded with a different program",
+            ))
+        }
+    }
+
+    /// After playing back `frame`, check that the machine is in the state
+    /// it was in when the movie was recorded, if there's a hash to check
+    /// against on that frame.
+    pub fn verify(&self, frame: u64, vm: &Chip8) -> Result<(), String> {
+        match self.frames.get(frame as usize).and_then(|f| f.hash) {
+            Some(hash) if hash != state_hash(vm) => Err(format!("desynced at frame {}", frame)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut quirks = self.quirks;
+        let names: Vec<&str> = QUIRK_NAMES
+            .iter()
+            .zip(quirk_flags(&mut quirks).iter())
+            .filter(|(_, on)| ***on)
+            .map(|(name, _)| *name)
+            .collect();
+
+        let mut text = format!(
+            "{}\nrom {}\nseed {}\nipf {}\nquirks {}\nframes\n",
+            HEADER,
+            to_hex(&self.rom_hash),
+            self.seed,
+            self.instructions_per_frame,
+            names.join(" ")
+        );
+        for frame in &self.frames {
+            match frame.hash {
+                Some(hash) => text += &format!("{:04x} {}\n", frame.keys, to_hex(&hash)),
+                None => text += &format!("{:04x}\n", frame.keys),
+            }
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err(String::from("not a movie, or from a newer version"));
+        }
+
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut instructions_per_frame = None;
+        let mut quirks = None;
+        for (number, line) in lines.by_ref() {
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("rom") => {
+                    let hash = fields.next().and_then(from_hex);
+                    rom_hash = Some(hash.ok_or_else(|| error("expected a SHA-1 hash"))?);
+                }
+                Some("seed") => {
+                    let value = fields.next().and_then(|seed| seed.parse().ok());
+                    seed = Some(value.ok_or_else(|| error("expected a seed"))?);
+                }
+                Some("ipf") => {
+                    let value = fields.next().and_then(|ipf| ipf.parse().ok());
+                    instructions_per_frame =
+                        Some(value.ok_or_else(|| error("expected instructions per frame"))?);
+                }
+                Some("quirks") => {
+                    let mut parsed = Quirks {
+                        shift_uses_vy: false,
+                        load_store_increments_i: false,
+                        jump_uses_vx: false,
+                        sprite_wrap: false,
+                        logic_resets_vf: false,
+                        display_wait: false,
+                    };
+                    for name in fields {
+                        let n = QUIRK_NAMES
+                            .iter()
+                            .position(|quirk| *quirk == name)
+                            .ok_or_else(|| error(&format!("unknown quirk '{}'", name)))?;
+                        *quirk_flags(&mut parsed)[n] = true;
+                    }
+                    quirks = Some(parsed);
+                }
+                Some("frames") => break,
+                None => {}
+                Some(other) => return Err(error(&format!("unknown header '{}'", other))),
+            }
+        }
+
+        let mut frames = vec![];
+        for (number, line) in lines {
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let mut fields = line.split_whitespace();
+            let keys = match fields.next() {
+                Some(keys) => {
+                    u16::from_str_radix(keys, 16).map_err(|_| error("expected the keys held"))?
+                }
+                None => continue,
+            };
+            let hash = match fields.next() {
+                Some(hash) => Some(from_hex(hash).ok_or_else(|| error("bad state hash"))?),
+                None => None,
+            };
+            frames.push(Frame { keys, hash });
+        }
+
+        let missing = |header: &str| format!("the {} header is missing", header);
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            instructions_per_frame: instructions_per_frame.ok_or_else(|| missing("ipf"))?,
+            frames,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|error| error.to_string())
+    }
+
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Movie::parse(&text)
+    }
+}
+
+// A hash of everything about the machine, from its save state.
+fn state_hash(vm: &Chip8) -> [u8; 20] {
+    hash::sha1(&vm.save_state().to_bytes())
+}
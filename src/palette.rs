@@ -0,0 +1,132 @@
+// The colors a frontend draws the display in. CHIP-8 and SUPER-CHIP only need
+// two, but XO-CHIP's two bit planes make a pixel one of four: unlit, lit on the
+// first plane, lit on the second, and lit on both.
+
+use crate::phosphor::Glow;
+use crate::profile::{parse_color, Profile};
+
+/// A color for each pixel value, as RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Palette {
+    /// The built-in palettes, by name, in the order a frontend cycles through them.
+    pub const PRESETS: [(&'static str, Palette); 6] = [
+        (
+            "dark",
+            Palette {
+                colors: [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]],
+            },
+        ),
+        (
+            "light",
+            Palette {
+                colors: [[255, 255, 255], [0, 0, 0], [85, 85, 85], [170, 170, 170]],
+            },
+        ),
+        // a green phosphor monitor
+        (
+            "green",
+            Palette {
+                colors: [[8, 24, 8], [51, 255, 102], [31, 153, 64], [140, 255, 170]],
+            },
+        ),
+        // an amber phosphor monitor
+        (
+            "amber",
+            Palette {
+                colors: [[24, 14, 0], [255, 176, 0], [153, 95, 0], [255, 214, 128]],
+            },
+        ),
+        // a greenish handheld LCD
+        (
+            "lcd",
+            Palette {
+                colors: [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]],
+            },
+        ),
+        // Octo's default colors, which a lot of XO-CHIP games were drawn for
+        (
+            "octo",
+            Palette {
+                colors: [[153, 102, 0], [255, 204, 0], [255, 102, 0], [102, 34, 0]],
+            },
+        ),
+    ];
+
+    /// Look up a preset by name (case insensitive), e.g. "amber".
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
+    }
+
+    /// A palette from two to four colors, unlit first. Whichever plane colors
+    /// are missing are filled in between the first two.
+    pub fn from_colors(colors: &[[u8; 3]]) -> Option<Palette> {
+        if colors.len() < 2 {
+            return None;
+        }
+        let (off, on) = (colors[0], colors[1]);
+        let mut palette = Palette {
+            colors: [off, on, mix(off, on, 2, 3), mix(off, on, 1, 3)],
+        };
+        for (n, color) in colors.iter().take(4).enumerate() {
+            palette.colors[n] = *color;
+        }
+        Some(palette)
+    }
+
+    /// A preset's name, or a custom palette as colors separated by commas, e.g.
+    /// `#000000,#33ff66`.
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::from_name(text) {
+            return Ok(palette);
+        }
+        let colors = text
+            .split(',')
+            .map(|color| {
+                parse_color(color.trim())
+                    .ok_or_else(|| format!("'{}' isn't a #rrggbb color", color))
+            })
+            .collect::<Result<Vec<[u8; 3]>, String>>()?;
+        Palette::from_colors(&colors)
+            .ok_or_else(|| String::from("a palette needs at least two colors"))
+    }
+
+    /// The palette a program's profile asks for, if it has colors.
+    pub fn from_profile(profile: &Profile) -> Option<Palette> {
+        Palette::from_colors(&profile.colors)
+    }
+
+    /// The color of a pixel from the display.
+    pub fn color(&self, pixel: u8) -> [u8; 3] {
+        self.colors[(pixel & 3) as usize]
+    }
+
+    /// The color of a pixel from the phosphor filter, somewhere between unlit
+    /// and its own color depending on how far it's faded.
+    pub fn faded(&self, glow: Glow) -> [u8; 3] {
+        let brightness = (glow.brightness.clamp(0.0, 1.0) * 255.0).round() as i32;
+        mix(self.colors[0], self.color(glow.value), brightness, 255)
+    }
+
+    /// A color just off from unlit, for the lines between pixels when there's
+    /// a grid.
+    pub fn grid(&self) -> [u8; 3] {
+        mix(self.colors[0], self.colors[1], 1, 8)
+    }
+}
+
+// Part of the way from one color to another, num/den of the way along.
+fn mix(from: [u8; 3], to: [u8; 3], num: i32, den: i32) -> [u8; 3] {
+    let mut mixed = [0u8; 3];
+    for (n, channel) in mixed.iter_mut().enumerate() {
+        let (a, b) = (from[n] as i32, to[n] as i32);
+        *channel = (a + (b - a) * num / den) as u8;
+    }
+    mixed
+}
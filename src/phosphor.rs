@@ -0,0 +1,109 @@
+// CHIP-8 programs move sprites by XORing them off and drawing them again, so a
+// frame often catches a sprite half erased and games flicker. A CRT's phosphor
+// took a moment to fade, which hid that. This does the same to the framebuffer:
+// a pixel lights up at once but fades out over the next few frames, so a
+// sprite that's erased and redrawn never quite goes out.
+//
+// It's a post-processing step any frontend can run on `Chip8::display` once a
+// frame, before drawing; the VM itself never sees it.
+
+use crate::display::{Display, LORES_HEIGHT, LORES_WIDTH};
+
+/// How lit one pixel looks after the filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glow {
+    /// The pixel's value the last time it was lit, which picks the color on
+    /// XO-CHIP.
+    pub value: u8,
+    /// 1.0 while it's lit, falling towards 0.0 after it goes out.
+    pub brightness: f32,
+}
+
+const DARK: Glow = Glow {
+    value: 0,
+    brightness: 0.0,
+};
+
+// Pixels this faint are as good as off.
+const CUTOFF: f32 = 1.0 / 64.0;
+
+pub struct Phosphor {
+    persistence: f32,
+    width: usize,
+    height: usize,
+    glow: Vec<Glow>,
+}
+
+impl Phosphor {
+    /// `persistence` is how much of a pixel's brightness is left after each frame
+    /// once it goes out, from 0 (the filter does nothing) to 1 (it never fades).
+    /// Around 0.5 hides most flicker without smearing movement much.
+    ///
+    /// Until the first update, it's a dark screen the size a machine starts at.
+    pub fn new(persistence: f32) -> Phosphor {
+        Phosphor {
+            persistence: persistence.clamp(0.0, 1.0),
+            width: LORES_WIDTH,
+            height: LORES_HEIGHT,
+            glow: vec![DARK; LORES_WIDTH * LORES_HEIGHT],
+        }
+    }
+
+    pub fn persistence(&self) -> f32 {
+        self.persistence
+    }
+
+    /// Take the next frame. Call this once per frame, at 60 Hz, since that's the
+    /// rate the fading is measured in.
+    pub fn update(&mut self, display: &Display) {
+        // a change of resolution clears the screen, and so does the phosphor
+        if (display.width(), display.height()) != (self.width, self.height) {
+            self.width = display.width();
+            self.height = display.height();
+            self.glow = vec![DARK; self.width * self.height];
+        }
+
+        for (glow, pixel) in self.glow.iter_mut().zip(display.pixels()) {
+            if *pixel != 0 {
+                *glow = Glow {
+                    value: *pixel,
+                    brightness: 1.0,
+                };
+            } else if glow.brightness * self.persistence >= CUTOFF {
+                glow.brightness *= self.persistence;
+            } else {
+                *glow = DARK;
+            }
+        }
+    }
+
+    /// How a pixel looks, as of the last update.
+    pub fn get(&self, x: usize, y: usize) -> Glow {
+        self.glow[y * self.width + x]
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The filtered frame for frontends that can only draw pixels on or off,
+    /// with any pixel at least half lit counting as on.
+    pub fn display(&self) -> Display {
+        let pixels: Vec<u8> = self
+            .glow
+            .iter()
+            .map(|glow| {
+                if glow.brightness >= 0.5 {
+                    glow.value
+                } else {
+                    0
+                }
+            })
+            .collect();
+        Display::from_pixels(self.width, self.height, &pixels).unwrap_or_default()
+    }
+}
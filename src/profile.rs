@@ -0,0 +1,270 @@
+// What we know about particular programs: which interpreter they were written
+// for, how fast they like to run, which keys they use and what colors they look
+// best in. Programs are picked out by the SHA-1 of the ROM.
+//
+// The database is in the format of the community chip-8-database
+// (https://github.com/chip-8/chip-8-database), so its `programs.json` can be
+// used as it is. That's a list of programs, each with a title and the ROMs for
+// it by hash:
+//
+//     [{
+//         "title": "Pong",
+//         "roms": {
+//             "b232ef880bd6060fb45fa6effed7edf0ae95670e": {
+//                 "platforms": ["modernChip8"],
+//                 "tickrate": 8,
+//                 "keys": {"up": 1, "down": 4},
+//                 "colors": {"pixels": ["#000000", "#ffffff"]},
+//                 "quirkyPlatforms": {"modernChip8": {"wrap": true}}
+//             }
+//         }
+//     }]
+//
+// The same thing written in TOML has the list under `programs`, i.e. `[[programs]]`
+// tables. Anything else the database says about a ROM is ignored.
+
+use crate::hash::from_hex;
+use crate::Quirks;
+
+use serde_json::{Map, Value};
+
+use std::fs;
+
+// The games that come with the emulator.
+const BUILTIN: &str = include_str!("profiles.json");
+
+/// Everything the database says about one ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub title: String,
+    /// The interpreters it runs on, best first, as the database's ids, e.g.
+    /// "originalChip8", "superchip" or "xochip".
+    pub platforms: Vec<String>,
+    /// The quirks of the first platform we know, with any the ROM changes.
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame.
+    pub tickrate: Option<usize>,
+    /// The CHIP-8 key for each thing the program does, e.g. ("up", 5).
+    pub keys: Vec<(String, u8)>,
+    /// The colors to draw with, unlit pixels first.
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Profile {
+    /// The name of the platform the quirks came from, e.g. "SUPER-CHIP".
+    pub fn platform(&self) -> Option<&'static str> {
+        self.platforms
+            .iter()
+            .find_map(|id| platform(id))
+            .map(|(name, _)| name)
+    }
+}
+
+// The name and quirks of each platform in the database that we can run.
+fn platform(id: &str) -> Option<(&'static str, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(("CHIP-8", Quirks::COSMAC_VIP)),
+        "modernChip8" => Some(("CHIP-8", Quirks::default())),
+        "chip48" => Some(("CHIP-48", Quirks::CHIP_48)),
+        "superchip1" | "superchip" => Some(("SUPER-CHIP", Quirks::SUPER_CHIP)),
+        "xochip" => Some(("XO-CHIP", Quirks::OCTO)),
+        _ => None,
+    }
+}
+
+/// A set of profiles, looked up by ROM hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileDb {
+    profiles: Vec<([u8; 20], Profile)>,
+}
+
+impl ProfileDb {
+    /// Profiles for the games in `games/`.
+    pub fn builtin() -> ProfileDb {
+        ProfileDb::parse_json(BUILTIN).expect("the built-in profiles should parse")
+    }
+
+    pub fn parse_json(text: &str) -> Result<ProfileDb, String> {
+        let document: Value = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        ProfileDb::from_value(&document)
+    }
+
+    pub fn parse_toml(text: &str) -> Result<ProfileDb, String> {
+        let document: toml::Value = text.parse().map_err(|error| format!("{}", error))?;
+        let document = serde_json::to_value(document).map_err(|error| error.to_string())?;
+        ProfileDb::from_value(&document)
+    }
+
+    /// Load a database from a file, which is TOML if it ends in `.toml` and JSON
+    /// otherwise.
+    pub fn load(path: &str) -> Result<ProfileDb, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        if path.ends_with(".toml") {
+            ProfileDb::parse_toml(&text)
+        } else {
+            ProfileDb::parse_json(&text)
+        }
+    }
+
+    /// Add the profiles from another database, replacing any we have for the
+    /// same ROMs.
+    pub fn merge(&mut self, other: ProfileDb) {
+        for (hash, profile) in other.profiles {
+            self.profiles.retain(|(h, _)| *h != hash);
+            self.profiles.push((hash, profile));
+        }
+    }
+
+    pub fn get(&self, rom_hash: [u8; 20]) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|(hash, _)| *hash == rom_hash)
+            .map(|(_, profile)| profile)
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    // The list of programs, either on its own or under `programs`.
+    fn from_value(document: &Value) -> Result<ProfileDb, String> {
+        let programs = match document {
+            Value::Array(programs) => programs,
+            Value::Object(top) => match top.get("programs") {
+                Some(Value::Array(programs)) => programs,
+                _ => return Err(String::from("expected a list of programs")),
+            },
+            _ => return Err(String::from("expected a list of programs")),
+        };
+
+        let mut db = ProfileDb::default();
+        for (n, program) in programs.iter().enumerate() {
+            let title = program
+                .get("title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("program {}: expected a title", n + 1))?;
+            let roms = program
+                .get("roms")
+                .and_then(Value::as_object)
+                .ok_or_else(|| format!("{}: expected roms", title))?;
+            for (hash, rom) in roms {
+                let error = |message: &str| format!("{}: {}: {}", title, hash, message);
+                let rom_hash = from_hex(hash).ok_or_else(|| error("not a SHA-1 hash"))?;
+                let rom = rom.as_object().ok_or_else(|| error("expected a table"))?;
+                let profile = parse_rom(title, rom).map_err(|message| error(&message))?;
+                db.merge(ProfileDb {
+                    profiles: vec![(rom_hash, profile)],
+                });
+            }
+        }
+        Ok(db)
+    }
+}
+
+fn parse_rom(title: &str, rom: &Map<String, Value>) -> Result<Profile, String> {
+    let platforms = match rom.get("platforms") {
+        None => vec![],
+        Some(platforms) => platforms
+            .as_array()
+            .and_then(|platforms| {
+                platforms
+                    .iter()
+                    .map(|id| id.as_str().map(String::from))
+                    .collect()
+            })
+            .ok_or_else(|| String::from("platforms: expected a list of names"))?,
+    };
+
+    let quirks = match platforms.iter().find_map(|id| Some((id, platform(id)?.1))) {
+        Some((id, mut quirks)) => {
+            // the ROM can disagree with its platform about some of them
+            let changes = rom.get("quirkyPlatforms").and_then(|q| q.get(id.as_str()));
+            if let Some(changes) = changes.and_then(Value::as_object) {
+                for (name, value) in changes {
+                    let on = value.as_bool().ok_or_else(|| {
+                        format!("quirkyPlatforms: {}: expected true or false", name)
+                    })?;
+                    set_quirk(&mut quirks, name, on);
+                }
+            }
+            Some(quirks)
+        }
+        None => None,
+    };
+
+    let tickrate = match rom.get("tickrate") {
+        None => None,
+        Some(tickrate) => Some(
+            tickrate
+                .as_u64()
+                .filter(|ipf| *ipf > 0)
+                .ok_or_else(|| String::from("tickrate: expected instructions per frame"))?
+                as usize,
+        ),
+    };
+
+    let mut keys = vec![];
+    if let Some(actions) = rom.get("keys") {
+        let actions = actions
+            .as_object()
+            .ok_or_else(|| String::from("keys: expected a table"))?;
+        for (action, key) in actions {
+            let key = key
+                .as_u64()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("keys: {}: expected a CHIP-8 key (0-15)", action))?;
+            keys.push((action.clone(), key as u8));
+        }
+    }
+
+    let mut colors = vec![];
+    if let Some(pixels) = rom.get("colors").and_then(|colors| colors.get("pixels")) {
+        let pixels = pixels
+            .as_array()
+            .ok_or_else(|| String::from("colors: expected a list of pixel colors"))?;
+        for color in pixels {
+            let rgb = color.as_str().and_then(parse_color);
+            colors.push(rgb.ok_or_else(|| format!("colors: bad color {}", color))?);
+        }
+    }
+
+    Ok(Profile {
+        title: title.to_string(),
+        platforms,
+        quirks,
+        tickrate,
+        keys,
+        colors,
+    })
+}
+
+// The database names quirks after what the interpreter does, so a few of them
+// mean the opposite of ours. Quirks we don't emulate are ignored.
+fn set_quirk(quirks: &mut Quirks, name: &str, on: bool) {
+    match name {
+        "shift" => quirks.shift_uses_vy = !on,
+        "memoryLeaveIUnchanged" => quirks.load_store_increments_i = !on,
+        "wrap" => quirks.sprite_wrap = on,
+        "jump" => quirks.jump_uses_vx = on,
+        "vblank" => quirks.display_wait = on,
+        "logic" => quirks.logic_resets_vf = on,
+        _ => {}
+    }
+}
+
+/// Parse a color written as `#rrggbb` (the `#` is optional).
+pub fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let mut rgb = [0u8; 3];
+    for (n, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&hex[n * 2..n * 2 + 2], 16).ok()?;
+    }
+    Some(rgb)
+}
@@ -0,0 +1,90 @@
+// The original CHIP-8 spec is ambiguous (or just plain inconsistent between
+// interpreters) about how some instructions behave. Different games were written
+// against different interpreters, so which behavior is "right" depends on the game.
+
+/// Toggles for each of the contested CHIP-8 behaviors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy and store the result in Vx, rather than shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// Fx55/Fx65 leave I pointing just past the last register they touched.
+    pub load_store_increments_i: bool,
+    /// Bnnn jumps to nnn + Vx (where x is the high nibble of nnn) instead of nnn + V0.
+    pub jump_uses_vx: bool,
+    /// Sprites drawn off the edge of the screen wrap around instead of being clipped.
+    pub sprite_wrap: bool,
+    /// 8xy1/8xy2/8xy3 reset VF to zero.
+    pub logic_resets_vf: bool,
+    /// Dxyn waits for the next vertical blank, so only one sprite is drawn per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter on the RCA COSMAC VIP.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        sprite_wrap: false,
+        logic_resets_vf: true,
+        display_wait: true,
+    };
+
+    /// CHIP-48, on the HP-48 graphing calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        sprite_wrap: false,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1, also on the HP-48. As far as these toggles go it's the same
+    /// as CHIP-48. Where they really differ can't be expressed here: CHIP-48's
+    /// Fx55/Fx65 leave I at I + x rather than I + x + 1 or unchanged, and
+    /// SUPER-CHIP waits for vertical blank only in low resolution.
+    pub const SUPER_CHIP: Quirks = Quirks::CHIP_48;
+
+    /// Octo, the modern interpreter most new games (and XO-CHIP) are written against.
+    pub const OCTO: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        sprite_wrap: true,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    /// The names accepted by [`Quirks::from_name`], paired with their presets.
+    pub const PRESETS: [(&'static str, Quirks); 4] = [
+        ("vip", Quirks::COSMAC_VIP),
+        ("chip48", Quirks::CHIP_48),
+        // the same quirks as chip48, under the name most SUPER-CHIP games are run with
+        ("schip", Quirks::SUPER_CHIP),
+        ("octo", Quirks::OCTO),
+    ];
+
+    /// Look up a preset by name (case insensitive), e.g. "vip" or "schip".
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        let name = name.to_lowercase();
+        Quirks::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, quirks)| *quirks)
+    }
+}
+
+// The behavior this interpreter has always had, which every bundled game is happy with.
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            sprite_wrap: false,
+            logic_resets_vf: false,
+            display_wait: false,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Rewinding. We take a snapshot of the machine every frame and keep the last
+// few seconds of them, so a frontend can play the machine backwards by popping
+// them off one at a time.
+//
+// Snapshots are kept in their serialized form, which leaves off the empty end
+// of memory. For most programs that's a few kilobytes a frame rather than 64K.
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::snapshot::Snapshot;
+
+use std::collections::VecDeque;
+
+/// A bounded ring buffer of per-frame snapshots.
+pub struct Rewind {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Keep enough frames to rewind the given number of seconds at 60 fps.
+    pub fn new(seconds: u32) -> Rewind {
+        Rewind::with_frames(seconds as usize * 60)
+    }
+
+    pub fn with_frames(capacity: usize) -> Rewind {
+        Rewind {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Call once per frame, before running it. Once the buffer is full the
+    /// oldest frame is dropped.
+    pub fn record(&mut self, vm: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(vm.save_state().to_bytes());
+    }
+
+    /// Put the machine back one frame. Returns false if there's nothing left to
+    /// rewind to.
+    pub fn step_back(&mut self, vm: &mut Chip8) -> Result<bool, Chip8Error> {
+        match self.frames.pop_back() {
+            Some(frame) => {
+                vm.load_state(&Snapshot::from_bytes(&frame)?)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// How many frames we can currently rewind.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Roughly how much memory the recorded frames are using, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.frames.iter().map(|frame| frame.len()).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // A program that adds 1 to V0 every frame.
+    fn counter() -> Chip8 {
+        let mut vm = Chip8::new(Quirks::default());
+        vm.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        vm
+    }
+
+    #[test]
+    fn stepping_back_with_nothing_recorded() {
+        let mut vm = counter();
+        let mut rewind = Rewind::with_frames(10);
+        assert!(rewind.is_empty());
+        assert!(!rewind.step_back(&mut vm).unwrap());
+
+        let mut rewind = Rewind::new(0);
+        rewind.record(&vm);
+        assert!(!rewind.step_back(&mut vm).unwrap());
+    }
+
+    #[test]
+    fn oldest_frames_are_dropped() {
+        let mut vm = counter();
+        let mut rewind = Rewind::with_frames(3);
+        for _ in 0..5 {
+            rewind.record(&vm);
+            vm.run_frame(2).unwrap();
+        }
+        assert_eq!(vm.registers()[0], 5);
+        assert_eq!(rewind.len(), 3);
+        assert!(rewind.memory_used() > 0);
+
+        // only the last three frames are left, and they come back newest first
+        for expected in [4, 3, 2] {
+            assert!(rewind.step_back(&mut vm).unwrap());
+            assert_eq!(vm.registers()[0], expected);
+        }
+        assert!(!rewind.step_back(&mut vm).unwrap());
+        assert_eq!(vm.registers()[0], 2);
+    }
+}
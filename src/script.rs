@@ -0,0 +1,98 @@
+// Scripted input for running programs without anyone at the keyboard.
+//
+// A script is a list of lines like `120 5 6`: starting at frame 120, hold down
+// keys 5 and 6 (in hex). The keys stay held until the next line takes over, and a
+// line with just a frame number lets go of everything. Blank lines and anything
+// after a `#` are ignored.
+
+/// The keys to hold down on each frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    // (first frame, keys held), sorted by frame
+    steps: Vec<(u64, Vec<u8>)>,
+}
+
+impl KeyScript {
+    pub fn parse(text: &str) -> Result<KeyScript, String> {
+        let mut steps: Vec<(u64, Vec<u8>)> = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let frame = fields
+                .next()
+                .unwrap_or("")
+                .parse::<u64>()
+                .map_err(|_| format!("line {}: expected a frame number", number + 1))?;
+
+            let mut keys = vec![];
+            for field in fields {
+                match u8::from_str_radix(field, 16) {
+                    Ok(key) if key < 16 => keys.push(key),
+                    _ => {
+                        return Err(format!(
+                            "line {}: '{}' isn't a key (0-F)",
+                            number + 1,
+                            field
+                        ))
+                    }
+                }
+            }
+
+            if let Some((previous, _)) = steps.last() {
+                if frame < *previous {
+                    return Err(format!("line {}: frames must be in order", number + 1));
+                }
+            }
+
+            steps.push((frame, keys));
+        }
+
+        Ok(KeyScript { steps })
+    }
+
+    /// The keys held down during the given frame.
+    pub fn keys_at(&self, frame: u64) -> &[u8] {
+        match self.steps.iter().rev().find(|(start, _)| *start <= frame) {
+            Some((_, keys)) => keys,
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_held_until_the_next_line() {
+        let script = KeyScript::parse("# a comment\n\n10 5 a  # hold 5 and A\n20\n30 F\n").unwrap();
+        assert!(script.keys_at(0).is_empty());
+        assert!(script.keys_at(9).is_empty());
+        assert_eq!(script.keys_at(10), [5, 0xA]);
+        assert_eq!(script.keys_at(19), [5, 0xA]);
+        assert!(script.keys_at(20).is_empty());
+        assert_eq!(script.keys_at(1000), [0xF]);
+    }
+
+    #[test]
+    fn an_empty_script_holds_nothing() {
+        assert!(KeyScript::parse("").unwrap().keys_at(0).is_empty());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |text| KeyScript::parse(text).unwrap_err();
+        assert_eq!(error("0 1\nsoon 2"), "line 2: expected a frame number");
+        assert_eq!(error("0 10"), "line 1: '10' isn't a key (0-F)");
+        assert_eq!(error("0 G"), "line 1: 'G' isn't a key (0-F)");
+        assert_eq!(
+            error("20 1\n# rewind\n10 2"),
+            "line 3: frames must be in order"
+        );
+    }
+}
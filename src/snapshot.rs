@@ -0,0 +1,235 @@
+// Save states. A Snapshot is everything needed to put a Chip8 back exactly the
+// way it was, and can be written out to a file.
+//
+// The file format is a header followed by the machine state, all big-endian:
+//
+//     magic       "C8SNAP"
+//     version     u16
+//     rom hash    20 bytes, the SHA-1 of the loaded program
+//     pc, i       u16 each
+//     dt, st      u8 each
+//     registers   16 bytes
+//     stack       u8 count, then a u16 per entry
+//     keys        u16, one bit per key
+//     rpl flags   16 bytes
+//     flags       u8, bit 0 halted, bit 1 drawn this frame and bit 2 audio
+//                 pattern loaded
+//     planes      u8
+//     pitch       u8
+//     audio       16 bytes
+//     rng         u64, the random number generator's state
+//     display     u16 width, u16 height, then a byte per pixel
+//     memory      u32 length, then that many bytes; the rest of memory is zero
+
+use crate::chip8::MEMORY_SIZE;
+use crate::display::Display;
+use crate::error::Chip8Error;
+
+use std::fs;
+
+const MAGIC: &[u8; 6] = b"C8SNAP";
+
+/// A copy of the entire machine state, from [`crate::Chip8::save_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub(crate) rom_hash: [u8; 20],
+    pub(crate) registers: [u8; 16],
+    pub(crate) memory: Vec<u8>,
+    pub(crate) stack: Vec<u16>,
+    pub(crate) i: u16,
+    pub(crate) dt: u8,
+    pub(crate) st: u8,
+    pub(crate) pc: u16,
+    pub(crate) display: Display,
+    pub(crate) keys_pressed: [bool; 16],
+    pub(crate) rpl_flags: [u8; 16],
+    pub(crate) halted: bool,
+    pub(crate) drawn_this_frame: bool,
+    pub(crate) planes: u8,
+    pub(crate) audio_pattern: [u8; 16],
+    pub(crate) audio_pattern_loaded: bool,
+    pub(crate) pitch: u8,
+    pub(crate) rng: u64,
+}
+
+impl Snapshot {
+    /// Bumped whenever the file format changes; older files are rejected.
+    pub const VERSION: u16 = 2;
+
+    /// SHA-1 of the program that was loaded when the snapshot was taken.
+    pub fn rom_hash(&self) -> [u8; 20] {
+        self.rom_hash
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256 + self.memory.len());
+
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&Snapshot::VERSION.to_be_bytes());
+        out.extend_from_slice(&self.rom_hash);
+
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.extend_from_slice(&self.i.to_be_bytes());
+        out.push(self.dt);
+        out.push(self.st);
+        out.extend_from_slice(&self.registers);
+
+        out.push(self.stack.len() as u8);
+        for address in &self.stack {
+            out.extend_from_slice(&address.to_be_bytes());
+        }
+
+        let keys = (0..16).fold(0u16, |keys, k| keys | (self.keys_pressed[k] as u16) << k);
+        out.extend_from_slice(&keys.to_be_bytes());
+
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(
+            self.halted as u8
+                | (self.drawn_this_frame as u8) << 1
+                | (self.audio_pattern_loaded as u8) << 2,
+        );
+        out.push(self.planes);
+        out.push(self.pitch);
+        out.extend_from_slice(&self.audio_pattern);
+        out.extend_from_slice(&self.rng.to_be_bytes());
+
+        out.extend_from_slice(&(self.display.width() as u16).to_be_bytes());
+        out.extend_from_slice(&(self.display.height() as u16).to_be_bytes());
+        out.extend_from_slice(self.display.pixels());
+
+        // most of memory is usually empty, so leave off the zeros at the end
+        let used = self
+            .memory
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |last| last + 1);
+        out.extend_from_slice(&(used as u32).to_be_bytes());
+        out.extend_from_slice(&self.memory[..used]);
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, Chip8Error> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(Chip8Error::InvalidSnapshot("not a save state"));
+        }
+        if reader.u16()? != Snapshot::VERSION {
+            return Err(Chip8Error::InvalidSnapshot(
+                "unsupported save state version",
+            ));
+        }
+
+        let mut rom_hash = [0u8; 20];
+        rom_hash.copy_from_slice(reader.take(20)?);
+
+        let pc = reader.u16()?;
+        let i = reader.u16()?;
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+        let mut registers = [0u8; 16];
+        registers.copy_from_slice(reader.take(16)?);
+
+        let stack_size = reader.u8()?;
+        let mut stack = vec![];
+        for _ in 0..stack_size {
+            stack.push(reader.u16()?);
+        }
+
+        let keys = reader.u16()?;
+        let mut keys_pressed = [false; 16];
+        for (k, pressed) in keys_pressed.iter_mut().enumerate() {
+            *pressed = keys & (1 << k) != 0;
+        }
+
+        let mut rpl_flags = [0u8; 16];
+        rpl_flags.copy_from_slice(reader.take(16)?);
+        let flags = reader.u8()?;
+        let planes = reader.u8()?;
+        let pitch = reader.u8()?;
+        let mut audio_pattern = [0u8; 16];
+        audio_pattern.copy_from_slice(reader.take(16)?);
+        let rng = reader.u64()?;
+
+        let width = reader.u16()? as usize;
+        let height = reader.u16()? as usize;
+        let display = Display::from_pixels(width, height, reader.take(width * height)?)
+            .ok_or(Chip8Error::InvalidSnapshot("bad display size"))?;
+
+        let memory_size = reader.u32()? as usize;
+        if memory_size > MEMORY_SIZE {
+            return Err(Chip8Error::InvalidSnapshot("memory is too large"));
+        }
+        let mut memory = reader.take(memory_size)?.to_vec();
+        memory.resize(MEMORY_SIZE, 0);
+
+        Ok(Snapshot {
+            rom_hash,
+            registers,
+            memory,
+            stack,
+            i,
+            dt,
+            st,
+            pc,
+            display,
+            keys_pressed,
+            rpl_flags,
+            halted: flags & 0x1 != 0,
+            drawn_this_frame: flags & 0x2 != 0,
+            planes,
+            audio_pattern,
+            audio_pattern_loaded: flags & 0x4 != 0,
+            pitch,
+            rng,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Chip8Error> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot, Chip8Error> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Pulls values off the front of a byte slice, failing if it runs out.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Chip8Error> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            return Err(Chip8Error::InvalidSnapshot("save state is truncated"));
+        }
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
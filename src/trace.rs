@@ -0,0 +1,163 @@
+// Execution traces: a line for every instruction executed, with the state of
+// the machine after it, for diffing runs against each other or seeing what led
+// up to a fault. A line looks like
+//
+//     1234 2A4 F033 LD B, V5           V 00 05 ... 00 I 0300 SP 1
+//
+// which is the number of instructions executed before this one, PC, opcode,
+// the disassembly, then V0-VF, I and the stack depth once it's run.
+
+use crate::disasm::disassemble_at;
+use crate::{Chip8, Chip8Error};
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+pub struct Tracer {
+    output: Box<dyn Write>,
+    // only instructions with a PC in here are traced
+    range: (u16, u16),
+    // with a ring buffer, the last lines traced and how many to keep; they're
+    // only written out if the program faults
+    ring: Option<(VecDeque<String>, usize)>,
+    cycles: u64,
+}
+
+impl Tracer {
+    /// Trace every instruction to `output`.
+    pub fn new(output: Box<dyn Write>) -> Tracer {
+        Tracer {
+            output,
+            range: (0, 0xFFFF),
+            ring: None,
+            cycles: 0,
+        }
+    }
+
+    /// Only trace instructions between `start` and `end` (inclusive).
+    pub fn only_between(mut self, start: u16, end: u16) -> Tracer {
+        self.range = (start, end);
+        self
+    }
+
+    /// Keep just the last `count` lines, and only write them out when the
+    /// program faults.
+    pub fn ring_buffer(mut self, count: usize) -> Tracer {
+        self.ring = Some((VecDeque::with_capacity(count), count));
+        self
+    }
+
+    /// How many instructions have been executed.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Execute the next instruction, like [`Chip8::execute_next_instruction`],
+    /// tracing it. Failing to write the trace is a [`Chip8Error::Io`].
+    pub fn execute(&mut self, vm: &mut Chip8) -> Result<(), Chip8Error> {
+        if vm.is_halted() {
+            return Ok(());
+        }
+
+        let pc = vm.pc();
+        let traced = self.range.0 <= pc && pc <= self.range.1;
+        // disassemble first, in case the instruction overwrites itself
+        let prefix = if traced {
+            let memory = vm.memory();
+            let byte = |a: u16| *memory.get(a as usize).unwrap_or(&0) as u16;
+            let opcode = byte(pc) << 8 | byte(pc.wrapping_add(1));
+            format!(
+                "{} {:03X} {:04X} {:<20}",
+                self.cycles,
+                pc,
+                opcode,
+                disassemble_at(memory, pc)
+            )
+        } else {
+            String::new()
+        };
+        self.cycles += 1;
+
+        match vm.execute_next_instruction() {
+            Ok(()) => {
+                if traced {
+                    let registers: Vec<String> = vm
+                        .registers()
+                        .iter()
+                        .map(|v| format!("{:02X}", v))
+                        .collect();
+                    let line = format!(
+                        "{} V {} I {:04X} SP {}",
+                        prefix,
+                        registers.join(" "),
+                        vm.i(),
+                        vm.stack().len()
+                    );
+                    self.write(line)?;
+                }
+                Ok(())
+            }
+            Err(error) => {
+                // the faulting instruction is always traced, whatever the range
+                let line = if traced {
+                    format!("{} FAULT: {}", prefix, error)
+                } else {
+                    format!("{:03X} FAULT: {}", pc, error)
+                };
+                if let Some((lines, _)) = self.ring.as_mut() {
+                    for line in lines.drain(..) {
+                        writeln!(self.output, "{}", line)?;
+                    }
+                }
+                writeln!(self.output, "{}", line)?;
+                self.output.flush()?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Run a frame like [`Chip8::run_frame`], tracing every instruction.
+    pub fn run_frame(
+        &mut self,
+        vm: &mut Chip8,
+        instructions_per_frame: usize,
+    ) -> Result<(), Chip8Error> {
+        for _ in 0..instructions_per_frame {
+            if vm.is_halted() {
+                break;
+            }
+            self.execute(vm)?;
+        }
+
+        vm.tick_timers();
+
+        Ok(())
+    }
+
+    fn write(&mut self, line: String) -> Result<(), Chip8Error> {
+        match self.ring.as_mut() {
+            Some((lines, count)) => {
+                if *count > 0 {
+                    if lines.len() == *count {
+                        lines.pop_front();
+                    }
+                    lines.push_back(line);
+                }
+            }
+            None => writeln!(self.output, "{}", line)?,
+        }
+        Ok(())
+    }
+}
+
+/// Parse a PC range for [`Tracer::only_between`] from the command line, as two
+/// hex addresses like `200-2FF`.
+pub fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let mut ends = text
+        .splitn(2, '-')
+        .map(|end| u16::from_str_radix(end.trim(), 16));
+    match (ends.next(), ends.next()) {
+        (Some(Ok(start)), Some(Ok(end))) if start <= end => Some((start, end)),
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+// Assembles each of the sources in games/SOURCES and checks that we get back
+// exactly the program that was built from it.
+
+use chip8_interpreter::asm::assemble;
+
+use std::fs;
+
+fn check(name: &str) {
+    let source = fs::read(format!("games/SOURCES/{}.SRC", name)).unwrap();
+    let program = assemble(&String::from_utf8_lossy(&source))
+        .unwrap_or_else(|error| panic!("{}.SRC: {}", name, error));
+    let expected = fs::read(format!("games/{}", name)).unwrap();
+    assert_eq!(
+        program.len(),
+        expected.len(),
+        "{} assembled to the wrong size",
+        name
+    );
+    for (offset, (actual, expected)) in program.iter().zip(&expected).enumerate() {
+        assert_eq!(
+            actual,
+            expected,
+            "{} differs at {:03X}",
+            name,
+            0x200 + offset
+        );
+    }
+}
+
+#[test]
+fn fifteen_puzzle() {
+    check("15PUZZLE");
+}
+
+#[test]
+fn blinky() {
+    check("BLINKY");
+}
+
+#[test]
+fn breakout() {
+    check("BREAKOUT");
+}
+
+#[test]
+fn brix() {
+    check("BRIX");
+}
+
+#[test]
+fn maze() {
+    check("MAZE");
+}
+
+#[test]
+fn pong() {
+    check("PONG");
+}
+
+#[test]
+fn pong2() {
+    check("PONG2");
+}
+
+#[test]
+fn syzygy() {
+    check("SYZYGY");
+}
+
+#[test]
+fn ufo() {
+    check("UFO");
+}
+
+#[test]
+fn vbrix() {
+    check("VBRIX");
+}
+
+#[test]
+fn org_pads_with_zeros() {
+    let program = assemble("    CLS\n    ORG #206\n    DB 1\n").unwrap();
+    assert_eq!(program, [0x00, 0xE0, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn org_below_the_program() {
+    assert_eq!(
+        assemble("    CLS\n    ORG #100\n    DB 1\n"),
+        Err(String::from(
+            "line 3: Program starts at #200, so nothing can go at #100"
+        ))
+    );
+    // just moving there is fine, as long as nothing gets written
+    assert_eq!(
+        assemble("    ORG #100\n    ORG #200\n    CLS\n"),
+        Ok(vec![0x00, 0xE0])
+    );
+}
+
+#[test]
+fn org_anywhere_in_64k() {
+    let program = assemble("    CLS\n    ORG #FFFF\n    DB 1\n").unwrap();
+    assert_eq!(program.len(), 0x10000 - 0x200);
+    assert_eq!(program[..2], [0x00, 0xE0]);
+    assert_eq!(program.last(), Some(&1));
+    assert!(assemble("    ORG #10000\n").is_err());
+}
+
+#[test]
+fn ds_past_the_end_of_memory() {
+    // an error, rather than trying to allocate all that first
+    assert_eq!(
+        assemble("    CLS\n    DS 99999999999\n"),
+        Err(String::from("line 2: Program runs past the end of memory"))
+    );
+    assert_eq!(
+        assemble("    DS #FE00\n").map(|program| program.len()),
+        Ok(0xFE00)
+    );
+}
@@ -0,0 +1,52 @@
+// Runs the test ROMs in games/ headlessly and compares the screen they finish
+// on with the golden images in tests/golden. After a deliberate change to what
+// a ROM draws, regenerate them with `UPDATE_GOLDEN=1 cargo test`.
+
+use chip8_interpreter::{Chip8, Quirks};
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Long enough for every test ROM to finish, at 8 instructions a frame.
+const FRAMES: usize = 300;
+
+fn check(rom: &str, quirks: Quirks) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut vm = Chip8::new(quirks);
+    vm.load_rom(&fs::read(root.join("games").join(rom)).unwrap())
+        .unwrap();
+    for _ in 0..FRAMES {
+        vm.run_frame(8).unwrap();
+    }
+
+    let golden = root.join("tests/golden").join(rom).with_extension("pbm");
+    let screen = vm.display().to_pbm();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &screen).unwrap();
+    }
+    let expected = fs::read_to_string(&golden).unwrap();
+    assert!(
+        screen == expected,
+        "{} doesn't match {}, it drew:\n{}",
+        rom,
+        golden.display(),
+        vm.display().to_ascii()
+    );
+}
+
+#[test]
+fn test_opcode() {
+    check("test_opcode.ch8", Quirks::default());
+}
+
+#[test]
+fn bc_test() {
+    // it expects Fx55/Fx65 to leave I alone
+    check("BC_test.ch8", Quirks::CHIP_48);
+}
+
+#[test]
+fn square_root_test() {
+    check("SquareRootTest.ch8", Quirks::default());
+}
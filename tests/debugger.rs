@@ -0,0 +1,147 @@
+// Drives the debugger the way a frontend would, through commands and frames.
+
+use chip8_interpreter::asm::assemble;
+use chip8_interpreter::debugger::{Action, Debugger};
+use chip8_interpreter::Chip8;
+
+const PROGRAM: &str = "
+        LD   V0, 0
+LOOP:   ADD  V0, 1
+        CALL SUB
+        JP   LOOP
+SUB:    LD   V1, V0
+        CALL INNER
+        RET
+INNER:  ADD  V2, 1
+        RET
+";
+
+fn setup() -> (Chip8, Debugger) {
+    let mut vm = Chip8::default();
+    vm.load_rom(&assemble(PROGRAM).unwrap()).unwrap();
+    (vm, Debugger::new())
+}
+
+// Run frames until the debugger stops, returning why.
+fn run(vm: &mut Chip8, debugger: &mut Debugger) -> String {
+    for _ in 0..100 {
+        if let Some(reason) = debugger.run_frame(vm, 10).unwrap() {
+            return reason;
+        }
+    }
+    panic!("never stopped");
+}
+
+#[test]
+fn stops_at_breakpoints() {
+    let (mut vm, mut debugger) = setup();
+    debugger.command(&mut vm, "break 208");
+    assert_eq!(debugger.command(&mut vm, "c").0, Action::Resume);
+
+    assert!(run(&mut vm, &mut debugger).starts_with("Breakpoint at 208"));
+    assert_eq!(vm.pc(), 0x208);
+
+    // continuing goes past the breakpoint we're sitting on, around to it again
+    debugger.command(&mut vm, "continue");
+    run(&mut vm, &mut debugger);
+    assert_eq!(vm.pc(), 0x208);
+    assert_eq!(vm.registers()[0], 2);
+}
+
+#[test]
+fn conditional_breakpoints() {
+    let (mut vm, mut debugger) = setup();
+    debugger.command(&mut vm, "break 204 if V0 == 5");
+    debugger.command(&mut vm, "continue");
+
+    assert_eq!(
+        run(&mut vm, &mut debugger),
+        "Breakpoint at 204 if V0 == 5\n204: CALL #208"
+    );
+    assert_eq!(vm.registers()[0], 5);
+}
+
+#[test]
+fn step_over_and_out() {
+    let (mut vm, mut debugger) = setup();
+    debugger.command(&mut vm, "until 204");
+    run(&mut vm, &mut debugger);
+
+    // next runs the whole call
+    assert_eq!(debugger.command(&mut vm, "next").0, Action::Resume);
+    run(&mut vm, &mut debugger);
+    assert_eq!(vm.pc(), 0x206);
+    assert_eq!(vm.registers()[2], 1);
+
+    // step into it, then finish gets back out
+    debugger.command(&mut vm, "step 3");
+    assert_eq!(vm.pc(), 0x208);
+    assert_eq!(vm.stack().len(), 1);
+    debugger.command(&mut vm, "finish");
+    run(&mut vm, &mut debugger);
+    assert_eq!(vm.pc(), 0x206);
+    assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn editing_the_machine() {
+    let (mut vm, mut debugger) = setup();
+    debugger.command(&mut vm, "set V3 2A");
+    debugger.command(&mut vm, "set I #300");
+    debugger.command(&mut vm, "poke 300 DE AD");
+
+    assert_eq!(vm.registers()[3], 0x2A);
+    assert_eq!(vm.i(), 0x300);
+    let (_, dump) = debugger.command(&mut vm, "dump 300 2");
+    assert_eq!(dump, "300: DE AD");
+
+    let (action, error) = debugger.command(&mut vm, "set V3 100");
+    assert_eq!(action, Action::Stay);
+    assert_eq!(error, "100 doesn't fit in a register");
+}
+
+#[test]
+fn watchpoints() {
+    let mut vm = Chip8::default();
+    let program = "
+        LD   I, SCORE
+        LD   V0, 123
+        LD   B, V0
+        LD   V2, [I]
+LOOP:   JP   LOOP
+SCORE:  DB   0, 0, 0
+";
+    vm.load_rom(&assemble(program).unwrap()).unwrap();
+    let mut debugger = Debugger::new();
+
+    // the tens digit is the second byte BCD writes
+    debugger.command(&mut vm, "watch 20B");
+    debugger.command(&mut vm, "continue");
+    assert_eq!(
+        run(&mut vm, &mut debugger),
+        "Watchpoint at 20B (write): 204: LD B, V0 (F033) wrote 02 to 20B\n206: LD V2, [I]"
+    );
+
+    // reading it back
+    debugger.command(&mut vm, "unwatch 20B");
+    debugger.command(&mut vm, "rwatch 20A 3");
+    let (_, output) = debugger.command(&mut vm, "step");
+    assert!(
+        output.contains("Watchpoint at 20A-20C (read): 206: LD V2, [I] (F265) read 01 from 20A")
+    );
+}
+
+#[test]
+fn single_steps_stop_at_watchpoints() {
+    let mut vm = Chip8::default();
+    vm.load_rom(&assemble("LD I, #300\nLD V0, 7\nLD [I], V0\nCLS").unwrap())
+        .unwrap();
+    let mut debugger = Debugger::new();
+    debugger.command(&mut vm, "watch 300");
+
+    assert_eq!(debugger.step(&mut vm).unwrap(), None);
+    assert_eq!(debugger.step(&mut vm).unwrap(), None);
+    let hit = debugger.step(&mut vm).unwrap().expect("a watchpoint hit");
+    assert!(hit.ends_with("wrote 07 to 300"), "{}", hit);
+    assert_eq!(debugger.step(&mut vm).unwrap(), None);
+}
@@ -0,0 +1,58 @@
+// Disassembles the bundled programs and checks that assembling the listings
+// gives back the same bytes.
+
+use chip8_interpreter::asm::assemble;
+use chip8_interpreter::disasm::{
+    disassemble, disassemble_linear, disassemble_program, format_listing,
+};
+
+use std::fs;
+
+fn round_trip(name: &str, rom: &[u8]) {
+    for (mode, lines) in [
+        ("traced", disassemble_program(rom, 0x200)),
+        ("linear", disassemble_linear(rom, 0x200)),
+    ] {
+        let listing = format_listing(&lines);
+        let program = assemble(&listing)
+            .unwrap_or_else(|error| panic!("{} ({}): {}\n{}", name, mode, error, listing));
+        assert_eq!(program, rom, "{} ({}) didn't reassemble", name, mode);
+    }
+}
+
+#[test]
+fn bundled_programs_reassemble() {
+    for entry in fs::read_dir("games").unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() {
+            round_trip(&path.display().to_string(), &fs::read(&path).unwrap());
+        }
+    }
+}
+
+#[test]
+fn jumps_outside_the_program_have_no_label() {
+    // a call into the interpreter's area, and a jump past the end
+    let rom = [0x20, 0x20, 0x13, 0x00, 0x00, 0xEE];
+    let listing = format_listing(&disassemble_program(&rom, 0x200));
+    assert!(listing.contains("CALL #020"), "{}", listing);
+    assert!(listing.contains("JP #300"), "{}", listing);
+    round_trip("out of range jumps", &rom);
+}
+
+#[test]
+fn jumps_into_the_middle_of_an_instruction() {
+    // 202 jumps to 203, halfway through itself, which decodes as SYS #300
+    let rom = [0x30, 0x00, 0x12, 0x03, 0x00, 0xEE];
+    let listing = format_listing(&disassemble_program(&rom, 0x200));
+    assert!(listing.contains("JP #203"), "{}", listing);
+    round_trip("overlapping code", &rom);
+}
+
+#[test]
+fn single_instructions() {
+    assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+    assert_eq!(disassemble(0x8006), "SHR V0");
+    assert_eq!(disassemble(0xF00A), "LD V0, K");
+    assert_eq!(disassemble(0x5001), "DW #5001");
+}
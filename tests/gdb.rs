@@ -0,0 +1,157 @@
+// Talks to the GDB stub over loopback, the way GDB would.
+
+use chip8_interpreter::asm::assemble;
+use chip8_interpreter::gdb::{GdbStub, Status};
+use chip8_interpreter::Chip8;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const PROGRAM: &str = "
+        LD   V0, 0
+LOOP:   ADD  V0, 1
+        LD   I, DATA
+        LD   [I], V0
+        JP   LOOP
+DATA:   DB   #00
+";
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+');
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = vec![];
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+// Start the stub on another thread, polling it like a frontend would until
+// it's killed, and connect to it.
+fn connect() -> (Client, JoinHandle<Chip8>) {
+    let mut vm = Chip8::default();
+    vm.load_rom(&assemble(PROGRAM).unwrap()).unwrap();
+    let mut stub = GdbStub::bind("127.0.0.1:0").unwrap();
+    let addr = stub.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        while stub.frame(&mut vm, 10).unwrap() != Status::Killed {
+            thread::sleep(Duration::from_millis(1));
+        }
+        vm
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn registers_and_memory() {
+    let (mut client, server) = connect();
+    assert!(client
+        .request("qSupported")
+        .contains("qXfer:features:read+"));
+    assert!(client
+        .request("qXfer:features:read:target.xml:0,1000")
+        .contains("<reg name=\"pc\" bitsize=\"16\""));
+    assert_eq!(client.request("?"), "S05");
+
+    // V0-VF, I, PC (little-endian), SP, DT and ST
+    assert_eq!(
+        client.request("g"),
+        format!("{}00000002000000", "00".repeat(16))
+    );
+    assert_eq!(client.request("m200,2"), "6000");
+
+    assert_eq!(client.request("P3=2a"), "OK");
+    assert_eq!(client.request("P11=0402"), "OK");
+    assert_eq!(client.request("p3"), "2a");
+    assert_eq!(client.request("p11"), "0402");
+    // SP is the stack depth, and the stack only goes 16 deep
+    assert_eq!(client.request("P12=02"), "OK");
+    assert_eq!(client.request("p12"), "02");
+    assert_eq!(client.request("P12=11"), "E01");
+    assert_eq!(client.request("p12"), "02");
+    assert_eq!(client.request("P12=00"), "OK");
+    assert_eq!(client.request("M300,2:beef"), "OK");
+    assert_eq!(client.request("m300,2"), "beef");
+    assert_eq!(client.request("m10000,1"), "E01");
+    assert_eq!(client.request("mffffffffffffffff,2"), "E01");
+    assert_eq!(client.request("Mffffffffffffffff,1:00"), "E01");
+    assert_eq!(client.request("Mffff,2:0000"), "E01");
+    assert_eq!(client.request("Z2,ffffffffffffffff,2"), "E01");
+
+    client.send("k");
+    let vm = server.join().unwrap();
+    assert_eq!(vm.registers()[3], 0x2a);
+    assert_eq!(vm.pc(), 0x204);
+    assert_eq!(vm.memory()[0x300], 0xbe);
+}
+
+#[test]
+fn stepping_and_breakpoints() {
+    let (mut client, server) = connect();
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p11"), "0202");
+
+    assert_eq!(client.request("Z0,206,2"), "OK");
+    client.send("c");
+    assert_eq!(client.reply(), "S05");
+    assert_eq!(client.request("p11"), "0602");
+    assert_eq!(client.request("p0"), "01");
+
+    // continuing goes past the breakpoint we're sitting on, around to it again
+    client.send("c");
+    assert_eq!(client.reply(), "S05");
+    assert_eq!(client.request("p0"), "02");
+
+    // a write watchpoint on DATA stops after the write
+    assert_eq!(client.request("z0,206,2"), "OK");
+    assert_eq!(client.request("Z2,20a,1"), "OK");
+    client.send("c");
+    assert_eq!(client.reply(), "T05watch:20a;");
+    assert_eq!(client.request("m20a,1"), "02");
+
+    // with nothing to stop at, it runs until interrupted
+    assert_eq!(client.request("z2,20a,1"), "OK");
+    client.send("c");
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.reply(), "S02");
+
+    client.send("k");
+    assert!(server.join().unwrap().registers()[0] > 2);
+}
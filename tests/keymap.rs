@@ -0,0 +1,71 @@
+// Keymap files: the global table, per-program overrides and bad files.
+
+use chip8_interpreter::keymap::{Keymap, KeymapConfig};
+
+const PONG: [u8; 20] = [0x11; 20];
+
+const CONFIG: &str = r#"
+[keys]
+5 = ["W", "Up"]
+8 = ["s", "Down"]
+4 = "Keypad 4"
+
+[roms.1111111111111111111111111111111111111111]
+name = "PONG"
+1 = "W"
+4 = ["S", "Q"]
+"#;
+
+#[test]
+fn default_layout() {
+    let keymap = Keymap::default();
+    assert_eq!(keymap.keys_for("Q"), vec![0x4]);
+    assert_eq!(keymap.keys_for("v"), vec![0xF]);
+    assert_eq!(keymap.keys_for("Up"), Vec::<u8>::new());
+    assert_eq!(keymap.host_keys(0x0), vec!["X"]);
+}
+
+#[test]
+fn global_table_changes_the_default() {
+    let keymap = KeymapConfig::parse(CONFIG)
+        .unwrap()
+        .keymap_for([0; 20], None);
+    assert_eq!(keymap.host_keys(0x5), vec!["W", "Up"]);
+    assert_eq!(keymap.keys_for("down"), vec![0x8]);
+    assert_eq!(keymap.keys_for("Keypad 4"), vec![0x4]);
+    // Q no longer presses 4, and untouched keys keep their defaults
+    assert_eq!(keymap.keys_for("Q"), Vec::<u8>::new());
+    assert_eq!(keymap.keys_for("X"), vec![0x0]);
+}
+
+#[test]
+fn programs_override_the_global_table() {
+    let keymap = KeymapConfig::parse(CONFIG).unwrap().keymap_for(PONG, None);
+    // W and S move from 5 and 8 to PONG's paddle keys
+    assert_eq!(keymap.keys_for("W"), vec![0x1]);
+    assert_eq!(keymap.host_keys(0x5), vec!["Up"]);
+    assert_eq!(keymap.host_keys(0x4), vec!["S", "Q"]);
+    assert_eq!(keymap.host_keys(0x8), vec!["Down"]);
+    assert_eq!(keymap.keys_for("Keypad 4"), Vec::<u8>::new());
+}
+
+#[test]
+fn parse_errors() {
+    assert!(KeymapConfig::parse("[keys\n").is_err());
+    assert_eq!(
+        KeymapConfig::parse("[keys]\nG = \"G\"\n"),
+        Err(String::from("keys: 'G' isn't a CHIP-8 key (0-F)"))
+    );
+    assert_eq!(
+        KeymapConfig::parse("[keys]\n1 = 1\n"),
+        Err(String::from("keys: 1: expected a key name or a list"))
+    );
+    assert_eq!(
+        KeymapConfig::parse("[roms.pong]\n1 = \"W\"\n"),
+        Err(String::from("roms.pong: expected a SHA-1 hash"))
+    );
+    assert_eq!(
+        KeymapConfig::parse("[colors]\n"),
+        Err(String::from("unknown section 'colors'"))
+    );
+}
@@ -0,0 +1,97 @@
+// Records a session of a game that uses random numbers, then plays it back.
+
+use chip8_interpreter::movie::Movie;
+use chip8_interpreter::{Chip8, Quirks};
+
+use std::fs;
+use std::path::Path;
+
+fn rom(name: &str) -> Vec<u8> {
+    fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("games")
+            .join(name),
+    )
+    .unwrap()
+}
+
+// Play TANK for a while, driving around, and record it.
+fn record() -> (Movie, Chip8) {
+    let mut vm = Chip8::new(Quirks::default());
+    vm.load_rom(&rom("TANK")).unwrap();
+    let mut movie = Movie::new(&vm, 8);
+    for frame in 0..300 {
+        vm.register_keydown(vec![[5, 4, 6, 8][frame / 50 % 4]].into_iter());
+        vm.run_frame(8).unwrap();
+        movie.record(&vm);
+    }
+    (movie, vm)
+}
+
+// Play a movie back from the start, returning the machine and the first desync.
+fn play(movie: &Movie) -> (Chip8, Result<(), String>) {
+    let mut vm = movie.machine();
+    vm.load_rom(&rom("TANK")).unwrap();
+    movie.check_rom(&vm).unwrap();
+    for frame in 0..movie.len() as u64 {
+        vm.register_keydown(movie.keys_at(frame).unwrap().into_iter());
+        vm.run_frame(movie.instructions_per_frame()).unwrap();
+        if let Err(error) = movie.verify(frame, &vm) {
+            return (vm, Err(error));
+        }
+    }
+    (vm, Ok(()))
+}
+
+#[test]
+fn playback_matches_the_recording() {
+    let (movie, recorded) = record();
+    assert_eq!(movie.len(), 300);
+    assert_eq!(movie.keys_at(120), Some(vec![6]));
+    assert_eq!(movie.keys_at(300), None);
+
+    let movie = Movie::parse(&movie.to_text()).unwrap();
+    let (played, result) = play(&movie);
+    assert_eq!(result, Ok(()));
+    assert_eq!(played.save_state(), recorded.save_state());
+}
+
+#[test]
+fn detects_desyncs() {
+    let (movie, _) = record();
+
+    // hold a different key for frames 60-89, so it's the game reacting to it
+    // that shows up in the next hash, not the keys themselves
+    let text = movie.to_text();
+    let lines: Vec<String> = text
+        .lines()
+        .enumerate()
+        .map(|(n, line)| match n {
+            n if (6 + 60..6 + 90).contains(&n) => line.replacen(&line[..4], "0100", 1),
+            _ => line.to_string(),
+        })
+        .collect();
+    let tampered = Movie::parse(&lines.join("\n")).unwrap();
+    assert_eq!(play(&tampered).1, Err(String::from("desynced at frame 119")));
+}
+
+#[test]
+fn wont_play_another_program() {
+    let (movie, _) = record();
+    let mut vm = movie.machine();
+    vm.load_rom(&rom("PONG")).unwrap();
+    assert!(movie.check_rom(&vm).is_err());
+}
+
+#[test]
+fn parse_errors() {
+    assert!(Movie::parse("PONG").is_err());
+    assert_eq!(
+        Movie::parse("chip8 movie 1\nseed 1\nipf 8\nquirks\nframes\n"),
+        Err(String::from("the rom header is missing"))
+    );
+    assert_eq!(
+        Movie::parse("chip8 movie 1\nquirks sprite_wrap bogus\n"),
+        Err(String::from("line 2: unknown quirk 'bogus'"))
+    );
+}
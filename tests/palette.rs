@@ -0,0 +1,76 @@
+// Palettes: the presets, custom colors and XO-CHIP's four colors.
+
+use chip8_interpreter::palette::Palette;
+use chip8_interpreter::profile::ProfileDb;
+
+#[test]
+fn presets() {
+    assert_eq!(Palette::parse("Amber"), Ok(Palette::PRESETS[3].1));
+    let dark = Palette::from_name("dark").unwrap();
+    assert_eq!(dark.color(0), [0, 0, 0]);
+    assert_eq!(dark.color(1), [255, 255, 255]);
+    for (_, palette) in Palette::PRESETS.iter() {
+        assert_ne!(palette.color(0), palette.color(1));
+    }
+}
+
+#[test]
+fn custom_colors() {
+    // the planes fill in between unlit and lit if they aren't given
+    let two = Palette::parse("#000000, #3366ff").unwrap();
+    assert_eq!(
+        two.colors,
+        [
+            [0, 0, 0],
+            [0x33, 0x66, 0xff],
+            [0x22, 0x44, 0xaa],
+            [0x11, 0x22, 0x55]
+        ]
+    );
+    assert_eq!(two.grid(), [0x06, 0x0c, 0x1f]);
+
+    let four = Palette::parse("102030,405060,708090,a0b0c0").unwrap();
+    assert_eq!(four.color(2), [0x70, 0x80, 0x90]);
+    assert_eq!(four.color(3), [0xa0, 0xb0, 0xc0]);
+}
+
+#[test]
+fn from_profile() {
+    let db = ProfileDb::parse_json(
+        r##"[{"title": "Pong", "roms": {"b232ef880bd6060fb45fa6effed7edf0ae95670e": {
+            "colors": {"pixels": ["#112233", "#445566"]}}}}]"##,
+    )
+    .unwrap();
+    let pong = db.get(pong_hash()).unwrap();
+    assert_eq!(
+        Palette::from_profile(pong).unwrap().color(1),
+        [0x44, 0x55, 0x66]
+    );
+    // the built-in profiles don't pick colors
+    assert_eq!(
+        Palette::from_profile(ProfileDb::builtin().get(pong_hash()).unwrap()),
+        None
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        Palette::parse("mauve"),
+        Err(String::from("'mauve' isn't a #rrggbb color"))
+    );
+    assert_eq!(
+        Palette::parse("#000000"),
+        Err(String::from("a palette needs at least two colors"))
+    );
+}
+
+// PONG's SHA-1
+fn pong_hash() -> [u8; 20] {
+    let hex = "b232ef880bd6060fb45fa6effed7edf0ae95670e";
+    let mut hash = [0u8; 20];
+    for (n, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[n * 2..n * 2 + 2], 16).unwrap();
+    }
+    hash
+}
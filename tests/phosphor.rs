@@ -0,0 +1,101 @@
+// The phosphor filter: pixels light at once and fade out after they're erased.
+
+use chip8_interpreter::palette::Palette;
+use chip8_interpreter::phosphor::{Glow, Phosphor};
+use chip8_interpreter::{Chip8, Quirks};
+
+// Draw the 0 digit in the top left corner, erase it, then switch to hires.
+const PROGRAM: [u8; 10] = [
+    0x60, 0x00, // LD V0, 0
+    0xF0, 0x29, // LD F, V0
+    0xD0, 0x05, // DRW V0, V0, 5
+    0x00, 0xE0, // CLS
+    0x00, 0xFF, // HIGH
+];
+
+// Run the next instruction and show the filter the result.
+fn step(vm: &mut Chip8, phosphor: &mut Phosphor) {
+    vm.execute_next_instruction().unwrap();
+    phosphor.update(vm.display());
+}
+
+fn brightness(phosphor: &Phosphor) -> f32 {
+    phosphor.get(0, 0).brightness
+}
+
+#[test]
+fn fades_after_erasing() {
+    let mut vm = Chip8::new(Quirks::default());
+    vm.load_rom(&PROGRAM).unwrap();
+    let mut phosphor = Phosphor::new(0.5);
+    for _ in 0..3 {
+        step(&mut vm, &mut phosphor);
+    }
+    assert_eq!(
+        phosphor.get(0, 0),
+        Glow {
+            value: 1,
+            brightness: 1.0
+        }
+    );
+    assert_eq!(phosphor.display(), *vm.display());
+
+    // erased, but still half lit, which counts as lit for on/off frontends
+    step(&mut vm, &mut phosphor);
+    assert_eq!(brightness(&phosphor), 0.5);
+    assert_eq!(phosphor.display().get(0, 0), 1);
+
+    phosphor.update(vm.display());
+    assert_eq!(brightness(&phosphor), 0.25);
+    assert_eq!(phosphor.display().get(0, 0), 0);
+
+    // and fades out completely before long
+    for _ in 0..10 {
+        phosphor.update(vm.display());
+    }
+    assert_eq!(phosphor.get(0, 0).value, 0);
+
+    // changing resolution starts over
+    step(&mut vm, &mut phosphor);
+    assert_eq!((phosphor.width(), phosphor.height()), (128, 64));
+}
+
+#[test]
+fn dark_before_the_first_update() {
+    let phosphor = Phosphor::new(0.5);
+    assert_eq!((phosphor.width(), phosphor.height()), (64, 32));
+    assert_eq!(brightness(&phosphor), 0.0);
+    assert_eq!(phosphor.get(63, 31).value, 0);
+    assert_eq!(phosphor.display(), *Chip8::new(Quirks::default()).display());
+}
+
+#[test]
+fn no_persistence_does_nothing() {
+    let mut vm = Chip8::new(Quirks::default());
+    vm.load_rom(&PROGRAM).unwrap();
+    let mut phosphor = Phosphor::new(0.0);
+    for _ in 0..3 {
+        step(&mut vm, &mut phosphor);
+    }
+    assert_eq!(brightness(&phosphor), 1.0);
+    step(&mut vm, &mut phosphor);
+    assert_eq!(brightness(&phosphor), 0.0);
+    assert_eq!(phosphor.display(), *vm.display());
+}
+
+#[test]
+fn fading_colors() {
+    let dark = Palette::from_name("dark").unwrap();
+    let half = Glow {
+        value: 1,
+        brightness: 0.5,
+    };
+    assert_eq!(dark.faded(half), [128, 128, 128]);
+    assert_eq!(
+        dark.faded(Glow {
+            value: 0,
+            brightness: 0.0
+        }),
+        dark.color(0)
+    );
+}
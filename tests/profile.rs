@@ -0,0 +1,143 @@
+// The profile database: the built-in profiles, the chip-8-database format in JSON
+// and TOML, and profiles being applied when a program's loaded.
+
+use chip8_interpreter::keymap::KeymapConfig;
+use chip8_interpreter::profile::ProfileDb;
+use chip8_interpreter::{Chip8, Quirks};
+
+use std::path::Path;
+
+const TANK: [u8; 20] = [
+    0x18, 0xb9, 0xd1, 0x5f, 0x4c, 0x15, 0x9e, 0x1f, 0x0e, 0xd5, 0x8c, 0x2d, 0x8e, 0xc1, 0xd8, 0x93,
+    0x25, 0xd3, 0xa3, 0xb6,
+];
+
+const JSON: &str = r##"[
+    {
+        "title": "Tank",
+        "authors": ["Someone"],
+        "roms": {
+            "18B9D15F4C159E1F0ED58C2D8EC1D89325D3A3B6": {
+                "file": "tank.ch8",
+                "platforms": ["megachip8", "superchip"],
+                "quirkyPlatforms": {"superchip": {"shift": false, "wrap": true}},
+                "tickrate": 30,
+                "keys": {"up": 2, "a": 5},
+                "colors": {"pixels": ["#102030", "#ffeedd"]}
+            }
+        }
+    }
+]"##;
+
+const TOML: &str = r##"
+[[programs]]
+title = "Tank"
+authors = ["Someone"]
+
+[programs.roms.18B9D15F4C159E1F0ED58C2D8EC1D89325D3A3B6]
+file = "tank.ch8"
+platforms = ["megachip8", "superchip"]
+quirkyPlatforms = { superchip = { shift = false, wrap = true } }
+tickrate = 30
+keys = { up = 2, a = 5 }
+colors = { pixels = ["#102030", "#ffeedd"] }
+"##;
+
+fn game(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("games")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn builtin_profiles() {
+    let db = ProfileDb::builtin();
+    let tank = db.get(TANK).unwrap();
+    assert_eq!(tank.title, "Tank");
+    assert_eq!(tank.platform(), Some("CHIP-8"));
+    assert_eq!(tank.quirks, Some(Quirks::default()));
+    assert!(tank.keys.contains(&(String::from("up"), 2)));
+    assert_eq!(db.get([0; 20]), None);
+}
+
+#[test]
+fn community_format() {
+    let db = ProfileDb::parse_json(JSON).unwrap();
+    let tank = db.get(TANK).unwrap();
+    assert_eq!(tank.platform(), Some("SUPER-CHIP"));
+    assert_eq!(
+        tank.quirks,
+        Some(Quirks {
+            shift_uses_vy: true,
+            sprite_wrap: true,
+            ..Quirks::SUPER_CHIP
+        })
+    );
+    assert_eq!(tank.tickrate, Some(30));
+    assert_eq!(
+        tank.keys,
+        vec![(String::from("a"), 5), (String::from("up"), 2)]
+    );
+    assert_eq!(tank.colors, vec![[0x10, 0x20, 0x30], [0xff, 0xee, 0xdd]]);
+
+    assert_eq!(ProfileDb::parse_toml(TOML), Ok(db));
+}
+
+#[test]
+fn user_profiles_replace_builtin_ones() {
+    let mut db = ProfileDb::builtin();
+    let builtin = db.len();
+    db.merge(ProfileDb::parse_json(JSON).unwrap());
+    assert_eq!(db.len(), builtin);
+    assert_eq!(db.get(TANK).unwrap().tickrate, Some(30));
+}
+
+#[test]
+fn applied_when_loading() {
+    // BC_test only passes with the CHIP-48 quirks
+    let mut vm = Chip8::new(Quirks::default());
+    vm.load_application(&game("BC_test.ch8")).unwrap();
+    assert_eq!(vm.profile().unwrap().title, "BC_test");
+    assert_eq!(vm.quirks(), Quirks::CHIP_48);
+
+    let mut db = ProfileDb::builtin();
+    db.merge(ProfileDb::parse_json(JSON).unwrap());
+    vm.load_application_with(&game("TANK"), &db).unwrap();
+    assert_eq!(vm.profile().unwrap().tickrate, Some(30));
+    assert!(vm.quirks().sprite_wrap);
+
+    // load_rom doesn't look anything up
+    vm.load_rom(&[0x00, 0xe0]).unwrap();
+    assert_eq!(vm.profile(), None);
+}
+
+#[test]
+fn profile_keys_go_on_the_arrows() {
+    let db = ProfileDb::builtin();
+    let keymap = KeymapConfig::default().keymap_for(TANK, db.get(TANK));
+    assert_eq!(keymap.keys_for("Up"), vec![0x2]);
+    assert_eq!(keymap.keys_for("Space"), vec![0x5]);
+    // the usual layout still works
+    assert_eq!(keymap.keys_for("W"), vec![0x5]);
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        ProfileDb::parse_json("{}"),
+        Err(String::from("expected a list of programs"))
+    );
+    assert_eq!(
+        ProfileDb::parse_json(r#"[{"title": "Tank", "roms": {"abc": {}}}]"#),
+        Err(String::from("Tank: abc: not a SHA-1 hash"))
+    );
+    let bad_key = JSON.replace(r#""up": 2"#, r#""up": 16"#);
+    assert_eq!(
+        ProfileDb::parse_json(&bad_key),
+        Err(String::from(
+            "Tank: 18B9D15F4C159E1F0ED58C2D8EC1D89325D3A3B6: keys: up: expected a CHIP-8 key (0-15)"
+        ))
+    );
+}
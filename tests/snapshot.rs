@@ -0,0 +1,97 @@
+// Save states: a round trip through the file format, and each way a file can be
+// rejected.
+
+use chip8_interpreter::{Chip8, Chip8Error, Quirks, Snapshot};
+
+use std::fs;
+use std::path::Path;
+
+fn rom(name: &str) -> Vec<u8> {
+    fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("games")
+            .join(name),
+    )
+    .unwrap()
+}
+
+// BRIX, a couple of seconds in, with the paddle moving.
+fn brix() -> Chip8 {
+    let mut vm = Chip8::with_seed(Quirks::default(), 1);
+    vm.load_rom(&rom("BRIX")).unwrap();
+    vm.register_keydown(vec![6].into_iter());
+    for _ in 0..120 {
+        vm.run_frame(10).unwrap();
+    }
+    vm
+}
+
+fn rejection(bytes: &[u8]) -> &'static str {
+    match Snapshot::from_bytes(bytes) {
+        Err(Chip8Error::InvalidSnapshot(reason)) => reason,
+        other => panic!("expected the save state to be rejected, got {:?}", other),
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut vm = brix();
+    let snapshot = vm.save_state();
+    let bytes = snapshot.to_bytes();
+    let screen = vm.display().to_ascii();
+
+    // play on, then go back
+    for _ in 0..60 {
+        vm.run_frame(10).unwrap();
+    }
+    assert_ne!(vm.display().to_ascii(), screen);
+
+    let loaded = Snapshot::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded, snapshot);
+    vm.load_state(&loaded).unwrap();
+    assert_eq!(vm.save_state(), snapshot);
+    assert_eq!(vm.display().to_ascii(), screen);
+
+    // and it carries on the same way as the machine it came from
+    let mut original = brix();
+    for _ in 0..60 {
+        vm.run_frame(10).unwrap();
+        original.run_frame(10).unwrap();
+    }
+    assert_eq!(vm.save_state(), original.save_state());
+}
+
+#[test]
+fn bad_magic() {
+    let mut bytes = brix().save_state().to_bytes();
+    bytes[0] = b'X';
+    assert_eq!(rejection(&bytes), "not a save state");
+    assert_eq!(rejection(b"PNG"), "save state is truncated");
+}
+
+#[test]
+fn bad_version() {
+    let mut bytes = brix().save_state().to_bytes();
+    bytes[6..8].copy_from_slice(&(Snapshot::VERSION + 1).to_be_bytes());
+    assert_eq!(rejection(&bytes), "unsupported save state version");
+}
+
+#[test]
+fn truncated() {
+    let bytes = brix().save_state().to_bytes();
+    for length in [8, 40, 100, bytes.len() - 1] {
+        assert_eq!(rejection(&bytes[..length]), "save state is truncated");
+    }
+}
+
+#[test]
+fn different_program() {
+    let snapshot = brix().save_state();
+    let mut vm = Chip8::new(Quirks::default());
+    vm.load_rom(&rom("PONG")).unwrap();
+    match vm.load_state(&snapshot) {
+        Err(Chip8Error::SnapshotRomMismatch) => {}
+        other => panic!("expected a ROM mismatch, got {:?}", other),
+    }
+    assert_eq!(snapshot.rom_hash(), brix().rom_hash());
+}
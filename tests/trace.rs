@@ -0,0 +1,151 @@
+// Traces small programs into memory and checks the lines that come out.
+
+use chip8_interpreter::trace::{parse_range, Tracer};
+use chip8_interpreter::{Chip8, Chip8Error};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// A Vec<u8> the tracer can own while the test still reads it.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+fn load(program: &[u16]) -> Chip8 {
+    let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    let mut vm = Chip8::default();
+    vm.load_rom(&rom).unwrap();
+    vm
+}
+
+// LD V0, #05, then ADD V0, #01 forever.
+const COUNTER: [u16; 3] = [0x6005, 0x7001, 0x1202];
+
+#[test]
+fn line_format() {
+    let output = Output::default();
+    let mut tracer = Tracer::new(Box::new(output.clone()));
+    let mut vm = load(&COUNTER);
+    for _ in 0..3 {
+        tracer.execute(&mut vm).unwrap();
+    }
+    assert_eq!(tracer.cycles(), 3);
+
+    let registers = |v0: &str| format!("V {} {}", v0, ["00"; 15].join(" "));
+    assert_eq!(
+        output.lines(),
+        [
+            format!(
+                "0 200 6005 LD V0, #05           {} I 0000 SP 0",
+                registers("05")
+            ),
+            format!(
+                "1 202 7001 ADD V0, #01          {} I 0000 SP 0",
+                registers("06")
+            ),
+            format!(
+                "2 204 1202 JP #202              {} I 0000 SP 0",
+                registers("06")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn only_between() {
+    let output = Output::default();
+    let mut tracer = Tracer::new(Box::new(output.clone())).only_between(0x202, 0x203);
+    let mut vm = load(&COUNTER);
+    for _ in 0..5 {
+        tracer.execute(&mut vm).unwrap();
+    }
+
+    // just the ADDs, though the cycle count includes everything
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(
+        lines[0].starts_with("1 202 7001 ADD V0, #01"),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[1].starts_with("3 202 7001 ADD V0, #01"),
+        "{}",
+        lines[1]
+    );
+}
+
+#[test]
+fn ring_buffer_is_only_written_on_a_fault() {
+    let output = Output::default();
+    let mut tracer = Tracer::new(Box::new(output.clone())).ring_buffer(2);
+    // three loads, then a return with nothing on the stack
+    let mut vm = load(&[0x6001, 0x6102, 0x6203, 0x00EE]);
+    for _ in 0..3 {
+        tracer.execute(&mut vm).unwrap();
+    }
+    assert!(output.lines().is_empty());
+
+    match tracer.execute(&mut vm) {
+        Err(Chip8Error::StackUnderflow) => {}
+        other => panic!("expected a stack underflow, got {:?}", other),
+    }
+    let lines = output.lines();
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[0].starts_with("1 202 6102 LD V1, #02"),
+        "{}",
+        lines[0]
+    );
+    assert!(
+        lines[1].starts_with("2 204 6203 LD V2, #03"),
+        "{}",
+        lines[1]
+    );
+    assert!(
+        lines[2].starts_with("3 206 00EE RET")
+            && lines[2].ends_with("FAULT: Returned from a subroutine with an empty stack"),
+        "{}",
+        lines[2]
+    );
+}
+
+#[test]
+fn faults_outside_the_range_are_still_traced() {
+    let output = Output::default();
+    let mut tracer = Tracer::new(Box::new(output.clone())).only_between(0x300, 0x3FF);
+    let mut vm = load(&[0x00EE]);
+    assert!(tracer.execute(&mut vm).is_err());
+    assert_eq!(
+        output.lines(),
+        ["200 FAULT: Returned from a subroutine with an empty stack"]
+    );
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(parse_range("200-2FF"), Some((0x200, 0x2FF)));
+    assert_eq!(parse_range("2ff - 300"), Some((0x2FF, 0x300)));
+    assert_eq!(parse_range("300-200"), None);
+    assert_eq!(parse_range("200"), None);
+}